@@ -1,5 +1,22 @@
 use bvh_anim_parser::parse::{load_bvh_from_file, load_bvh_from_string};
-use bvh_anim_parser::types::{Endsite, Joint};
+use bvh_anim_parser::types::{Endsite, Joint, Position};
+use bvh_anim_parser::units::Unit;
+use bvh_anim_parser::sample::{Interpolation, WrapMode};
+use bvh_anim_parser::clip::RootAlignment;
+use bvh_anim_parser::blend::RotationBlend;
+use bvh_anim_parser::mask::JointMask;
+use bvh_anim_parser::mirror::{MirrorAxis, NamePairing};
+use bvh_anim_parser::retarget::JointMap;
+use bvh_anim_parser::humanoid::HumanoidBone;
+use bvh_anim_parser::builder::{AnimationBuilder, SkeletonBuilder};
+use bvh_anim_parser::derivatives::{Difference, RotationSpace};
+use bvh_anim_parser::filter::Filter;
+use bvh_anim_parser::contact::ContactSettings;
+use bvh_anim_parser::ik::{AngleLimits, IkChain, IkSolver, TwoBoneChain};
+use bvh_anim_parser::reconstruct::reconstruct_from_positions;
+use bvh_anim_parser::keypoints::{import_keypoints, parse_keypoints_json, CoordinateSystem, KeypointLayout};
+use bvh_anim_parser::compress::CompressionSettings;
+use bvh_anim_parser::looping::LoopSettings;
 use bvh_anim_parser::visualize::visualize_skeleton;
 
 fn main() {
@@ -10,7 +27,7 @@ fn main() {
     // or from a string
     // (`include_str` works at compile time and so has a different base path than `load_bvh_from_file` - ignore the difference)
     let bvh_string: &str = include_str!("./test_anim_sword_attack.bvh");
-    let (bvh_metadata, bvh_data) = load_bvh_from_string(bvh_string);
+    let (mut bvh_metadata, mut bvh_data) = load_bvh_from_string(bvh_string);
 
 
     //////////////////////////////// fields of BvhMetadata ////////////////
@@ -138,7 +155,322 @@ fn main() {
         let cgmath::Quaternion { s, v } = left_shoulder_rest_rot;
         let cgmath::Vector3 { x, y, z } = v;
     }
-    
+
+    //////////////////////////////// units ////////////////
+    {
+        // the unit can be guessed from the height of a (human) skeleton
+        let unit = bvh_data.guess_unit(&bvh_metadata);
+
+        // rescale offsets, endsites, root translations and global positions (e.g. to meters)
+        bvh_data.to_meters(&mut bvh_metadata, unit);
+        // or by an arbitrary factor
+        bvh_data.rescale(&mut bvh_metadata, 100.0);
+    }
+
+    //////////////////////////////// sampling at arbitrary time ////////////////
+    {
+        // sample in between frames 23 and 24 (root translation is lerped, rotations are slerped, globals are recalculated with FK)
+        let time = 23.5 * bvh_metadata.frame_time;
+        let _pose = bvh_data.sample(&bvh_metadata, time, WrapMode::Clamp, Interpolation::Linear);
+
+        // looping with cubic (squad) interpolation, or a single frame
+        let _pose = bvh_data.sample(&bvh_metadata, 10.0, WrapMode::Loop, Interpolation::Cubic);
+        let _frame = bvh_data.pose_at_frame(23);
+    }
+
+    //////////////////////////////// resampling to a different frame rate ////////////////
+    {
+        // downsampling low-pass filters the animation first, upsampling interpolates
+        let (metadata_30fps, data_30fps) = bvh_data.resample(&bvh_metadata, 30.0);
+        let (_metadata_60fps, _data_60fps) = data_30fps.resample(&metadata_30fps, 60.0);
+    }
+
+    //////////////////////////////// slicing, concatenating, reversing, repeating ////////////////
+    {
+        let (metadata_a, data_a) = bvh_data.slice(&bvh_metadata, 0..100);
+        let (metadata_b, data_b) = bvh_data.slice(&bvh_metadata, 300..400);
+
+        // the second clip continues from where (and in which direction) the first one ends
+        let (metadata_ab, data_ab) = data_a.concat(&metadata_a, &data_b, &metadata_b, RootAlignment::PositionAndHeading);
+        let (metadata_rev, data_rev) = data_ab.reverse(&metadata_ab);
+        let (_metadata_loop, _data_loop) = data_rev.repeat(&metadata_rev, 3, RootAlignment::Position);
+    }
+
+    //////////////////////////////// blending and crossfading ////////////////
+    {
+        let (metadata_a, data_a) = bvh_data.slice(&bvh_metadata, 0..200);
+        let (metadata_b, data_b) = bvh_data.slice(&bvh_metadata, 300..500);
+
+        // 30% of the second clip blended into the first one
+        let (_metadata_blend, _data_blend) = data_a.blend(&metadata_a, &data_b, &metadata_b, 0.3, RotationBlend::Slerp);
+
+        // smooth transition over 40 frames
+        let (metadata_fade, data_fade) = data_a.crossfade(&metadata_a, &data_b, &metadata_b, 40, RotationBlend::Nlerp, RootAlignment::Position);
+
+        // after editing local rotations or the root translation, recalculate global pose with forward kinematics
+        let mut data_fade = data_fade;
+        data_fade.pose_local_rotations[4][0] = cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        data_fade.recalculate_global_pose(&metadata_fade);
+    }
+
+    //////////////////////////////// masked blending and additive layers ////////////////
+    {
+        let (metadata_a, data_a) = bvh_data.slice(&bvh_metadata, 0..200);
+        let (metadata_b, data_b) = bvh_data.slice(&bvh_metadata, 300..500);
+
+        // upper body (Spine2 and everything below it in the hierarchy) from the second clip, the rest from the first
+        let upper_body = JointMask::subtree(&metadata_a, "Spine2");
+        let (_, _data_masked) = data_a.blend_masked(&metadata_a, &data_b, &metadata_b, &upper_body, RotationBlend::Slerp);
+
+        // soft seam: weights ramp up over 2 levels of the hierarchy
+        let _soft_upper_body = JointMask::subtree_with_falloff(&metadata_a, "Spine2", 2);
+
+        // additive layer: difference between a clip and a reference pose, added on top of another clip
+        let layer = data_b.make_additive(&metadata_b, &data_b.pose_at_frame(0));
+        let (_metadata_add, _data_add) = data_a.apply_additive(&metadata_a, &layer, 0.5, &upper_body);
+    }
+
+    //////////////////////////////// mirroring ////////////////
+    {
+        // swaps Left*/Right* joint tracks and reflects the motion along the X axis
+        let pairing = NamePairing::default();
+        let _mirrored = bvh_data.mirror(&bvh_metadata, MirrorAxis::X, &pairing).expect("skeleton is symmetric");
+        // index of the opposite joint of every joint
+        let _pairs = bvh_metadata.mirror_pairs(&pairing).unwrap();
+    }
+
+    //////////////////////////////// root motion ////////////////
+    {
+        // facing direction of the character in the root's local frame (guessed from the legs and the spine)
+        let forward = bvh_data.root_forward_axis(&bvh_metadata);
+
+        // ground-projected root position + heading (yaw) of every frame
+        let _trajectory = bvh_data.extract_root_trajectory(&bvh_metadata, forward);
+
+        // in-place animation (stays above the origin, facing +Z) and back
+        let (trajectory, in_place) = bvh_data.to_in_place(&bvh_metadata, forward);
+        let _restored = in_place.apply_root_trajectory(&bvh_metadata, &trajectory);
+    }
+
+    //////////////////////////////// character-relative encoding (PFNN, MotionVAE, ...) ////////////////
+    {
+        let forward = bvh_data.root_forward_axis(&bvh_metadata);
+        // per-frame root deltas (dx, dz, dyaw) in the character frame + root-relative joint positions and local rotations
+        let encoding = bvh_data.encode_character_frame(&bvh_metadata, forward);
+        let _features: Vec<f64> = encoding.frame_features(100);
+
+        // decoding integrates the deltas back into world space
+        let _decoded = encoding.decode(&bvh_metadata, &bvh_data);
+    }
+
+    //////////////////////////////// retargeting ////////////////
+    {
+        // target skeleton: here the same one in meters, but it could be any rig loaded from a .bvh file
+        let (mut target_metadata, mut target_data) = load_bvh_from_string(bvh_string);
+        target_data.to_meters(&mut target_metadata, Unit::Centimeters);
+
+        // source joint name -> target joint name
+        let joint_map = JointMap::by_same_names(&bvh_metadata, &target_metadata);
+        let _custom_map = JointMap::from_pairs(&[("Hips", "Hips"), ("Spine", "Spine")]);
+
+        // root translation is scaled by the leg length ratio (here 0.01)
+        let (_retargeted_metadata, _retargeted_data) = bvh_data.retarget(&bvh_metadata, &target_metadata, &target_data, &joint_map, None);
+    }
+
+    //////////////////////////////// humanoid mapping ////////////////
+    {
+        // auto-detects the naming preset which maps the most canonical bones
+        let mapping = bvh_metadata.humanoid_mapping();
+        let _left_forearm = mapping.get(HumanoidBone::LeftLowerArm);
+
+        // joint map for retargeting between two (differently named) humanoids
+        let _joint_map = JointMap::between_humanoids(&bvh_metadata, &bvh_metadata);
+    }
+
+    //////////////////////////////// humanoid auto-labeling ////////////////
+    {
+        // label bones from the hierarchy alone (no joint names); geometry of frame 0 decides left/right
+        let detection = bvh_data.detect_humanoid(&bvh_metadata, Some(0));
+        for assignment in detection.assignments.iter() {
+            println!("{:?}: {} ({:.2})", assignment.bone, bvh_metadata.joints[assignment.joint].name, assignment.confidence);
+        }
+    }
+
+    //////////////////////////////// skeleton simplification ////////////////
+    {
+        // drop thumbs and hand end joints - the hands become leaf joints with endsites
+        let (_small_metadata, _small_data, _remap) = bvh_data.keep_joints(&bvh_metadata, |joint| {
+            !joint.name.contains("Thumb") && !joint.name.ends_with("HandEnd")
+        });
+
+        // or remove joints by index
+        let spine1 = bvh_metadata.find_joint_by_name("Spine1").index;
+        let (_small_metadata, _small_data, _remap) = bvh_data.remove_joints(&bvh_metadata, &[spine1]);
+    }
+
+    //////////////////////////////// skeleton editing ////////////////
+    {
+        // weapon joint in the right hand
+        let hand = bvh_metadata.find_joint_by_name("RightHand").index;
+        let (_edited_metadata, _edited_data, _remap) =
+            bvh_data.add_joint(&bvh_metadata, hand, "Sword", Position::new(0.0, 100.0, 0.0));
+
+        // root joint on the ground under the hips
+        let hips_height = bvh_data.rest_global_positions[0].y;
+        let (mut edited_metadata, _edited_data, _remap) =
+            bvh_data.insert_root_joint(&bvh_metadata, "Root", Position::new(0.0, hips_height, 0.0));
+
+        // rename by regex (prefix every joint with a namespace)
+        let _renamed = edited_metadata.rename_joints("^", "sword:").expect("new names are unique");
+
+        // reparent the left hand under the right hand, world motion stays the same where it moves rigidly
+        let left_hand = bvh_metadata.find_joint_by_name("LeftHand").index;
+        let (_edited_metadata, _edited_data, _remap) = bvh_data.reparent_joint(&bvh_metadata, left_hand, hand);
+    }
+
+    //////////////////////////////// building skeletons and animations in code ////////////////
+    {
+        // a two-bone arm, rotating its shoulder around Z by 90 degrees
+        let (_arm_metadata, _arm_data) = SkeletonBuilder::new("Shoulder", Position::new(0.0, 0.0, 0.0))
+            .joint("Elbow", "Shoulder", Position::new(10.0, 0.0, 0.0))
+            .endsite("Elbow", Position::new(10.0, 0.0, 0.0))
+            .animation(1.0 / 60.0)
+            .push_frame_euler(Position::new(0.0, 0.0, 0.0), &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+            .push_frame_euler(Position::new(0.0, 0.0, 0.0), &[90.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+            .build();
+
+        // new frames for an existing skeleton
+        let local_rotations: Vec<_> = bvh_data.pose_local_rotations.iter().map(|track| track[0]).collect();
+        let (_built_metadata, _built_data) = AnimationBuilder::new(&bvh_metadata, &bvh_data, bvh_metadata.frame_time)
+            .push_frame(bvh_data.pose_global_positions[0][0], &local_rotations)
+            .build();
+    }
+
+    //////////////////////////////// changing the rest pose ////////////////
+    {
+        // the rest pose of this file has all the bones along +X - use the first frame as the rest pose instead
+        let (rebased_metadata, rebased_data) = bvh_data.rebase_rest_pose(&bvh_metadata, 0);
+
+        // then straighten the arms into a T-pose or an A-pose
+        let (_t_metadata, _t_data) = rebased_data.rest_to_t_pose(&rebased_metadata);
+        let (_a_metadata, _a_data) = rebased_data.rest_to_a_pose(&rebased_metadata, 45.0);
+    }
+
+    //////////////////////////////// velocities and accelerations ////////////////
+    {
+        // [joint][frame], optionally smoothed before differentiating
+        let _velocities = bvh_data.linear_velocities(&bvh_metadata, Difference::Central, None);
+        let _accelerations = bvh_data.linear_accelerations(&bvh_metadata, Difference::Central, Some(2.0));
+        // rotation axis * radians per second
+        let _angular = bvh_data.angular_velocities(&bvh_metadata, RotationSpace::Global, Difference::Forward, None);
+    }
+
+    //////////////////////////////// quaternion continuity and filtering ////////////////
+    {
+        let mut continuous = bvh_data.clone();
+        continuous.enforce_quaternion_continuity();
+
+        // rotations are filtered in their tangent space, so sign flips don't matter
+        let filter = Filter::Butterworth { cutoff: 6.0 };
+        let _smoothed = continuous.filter_rotations(&bvh_metadata, filter).filter_root_positions(&bvh_metadata, filter);
+        let _filtered = Filter::SavitzkyGolay { window: 7, order: 2 }.apply_to_positions(&bvh_data.pose_global_positions[0], bvh_metadata.frame_time);
+    }
+
+    //////////////////////////////// foot contacts ////////////////
+    {
+        // heights are measured against the rest pose, which has to be a standing pose (the one of this file isn't)
+        let (standing_metadata, standing_data) = bvh_data.rebase_rest_pose(&bvh_metadata, 0);
+        // this file is in centimeters, the character is ~170 cm tall
+        let settings = ContactSettings::for_character_height(170.0);
+        let contacts = standing_data.detect_humanoid_foot_contacts(&standing_metadata, &settings);
+        let left_foot = bvh_metadata.find_joint_by_name("LeftFoot").index;
+        let _left_contacts = contacts.of_joint(left_foot).unwrap();
+
+        // contacts can be saved next to the animation
+        let _csv = contacts.to_csv(&bvh_metadata);
+    }
+
+    //////////////////////////////// inverse kinematics and foot-skate cleanup ////////////////
+    {
+        let chain = TwoBoneChain {
+            root: bvh_metadata.find_joint_by_name("LeftUpLeg").index,
+            middle: bvh_metadata.find_joint_by_name("LeftLeg").index,
+            end: bvh_metadata.find_joint_by_name("LeftFoot").index,
+        };
+        // lift the left foot by 10 cm, keeping its orientation and the knee direction
+        let mut lifted = bvh_data.clone();
+        let target = bvh_data.pose_global_positions[chain.end][10] + Position::new(0.0, 10.0, 0.0);
+        let knee = bvh_data.pose_global_positions[chain.middle][10];
+        let foot_rotation = bvh_data.pose_global_rotations[chain.end][10];
+        lifted.solve_two_bone_ik(&bvh_metadata, 10, chain, target, Some(foot_rotation), Some(knee));
+
+        // smoothing the root makes the feet slide - pin them back during contacts
+        let (standing_metadata, standing_data) = bvh_data.rebase_rest_pose(&bvh_metadata, 0);
+        let contacts = standing_data.detect_humanoid_foot_contacts(&standing_metadata, &ContactSettings::for_character_height(170.0));
+        let sliding = bvh_data.filter_root_positions(&bvh_metadata, Filter::Gaussian { sigma: 8.0 });
+        let _fixed = sliding.fix_humanoid_foot_skate(&bvh_metadata, &contacts, 5);
+    }
+
+    //////////////////////////////// IK chains ////////////////
+    {
+        // reach 10 cm further with the right hand, using the whole arm; the elbow can't rotate at all
+        let shoulder = bvh_metadata.find_joint_by_name("RightShoulder").index;
+        let elbow = bvh_metadata.find_joint_by_name("RightForeArm").index;
+        let hand = bvh_metadata.find_joint_by_name("RightHand").index;
+        let locked = AngleLimits { min: Position::new(0.0, 0.0, 0.0), max: Position::new(0.0, 0.0, 0.0) };
+        let chain = IkChain::from_joints(&bvh_metadata, shoulder, hand).with_limits(elbow, locked);
+        let target = bvh_data.pose_global_positions[hand][50] + Position::new(0.0, 10.0, 0.0);
+        let mut solved = bvh_data.clone();
+        solved.solve_ik(&bvh_metadata, 50, &chain, IkSolver::Ccd { iterations: 50, tolerance: 1e-3 }, target, None);
+        solved.solve_ik(&bvh_metadata, 50, &chain, IkSolver::Fabrik { iterations: 50, tolerance: 1e-3 }, target, None);
+    }
+
+    //////////////////////////////// rotations from joint positions ////////////////
+    {
+        // only global positions are known (e.g. 3D keypoints) - recover the rotations
+        let (_reconstructed_metadata, _reconstructed_data) =
+            reconstruct_from_positions(&bvh_metadata, &bvh_data.rest_local_positions, &bvh_data.pose_global_positions);
+    }
+
+    //////////////////////////////// importing 3D keypoints ////////////////
+    {
+        // keypoints can be parsed from JSON (or CSV, see `parse_keypoints_csv`), or loaded with `load_keypoints_from_file`
+        let frames = parse_keypoints_json(r#"{"frames": [[[0, 1, 2], {"x": 3, "y": 4, "z": 5}]]}"#);
+        let num_keypoints = frames[0].len();
+        println!("parsed {} frames of {} keypoints", frames.len(), num_keypoints);
+
+        // a layout provides the skeleton template and the usual coordinate system
+        let mut settings = KeypointLayout::Human36M.default_settings();
+        settings.coordinate_system = CoordinateSystem::YUp;
+        let frames = vec![vec![Position::new(0.0, 0.0, 0.0); KeypointLayout::Human36M.num_keypoints()]];
+        let (_keypoints_metadata, _keypoints_data) = import_keypoints(KeypointLayout::Human36M, &frames, &settings);
+    }
+
+    //////////////////////////////// compression ////////////////
+    {
+        // keyframe reduction within 0.5 cm and 0.01 rad (in global space), with 16-bit quantized rotations
+        let settings = CompressionSettings { max_position_error: 0.5, max_angle_error: 0.01, quantization_bits: Some(16) };
+        let (compressed, report) = bvh_data.compress(&bvh_metadata, &settings);
+        println!(
+            "compressed {} -> {} bytes ({:.1}x), max errors {:.3} cm, {:.4} rad",
+            report.original_bytes, report.compressed_bytes, report.compression_ratio, report.max_position_error, report.max_angle_error
+        );
+
+        // decompress to a full-rate animation
+        let (_decompressed_metadata, _decompressed_data) = compressed.decompress(&bvh_metadata, &bvh_data);
+    }
+
+    //////////////////////////////// loops ////////////////
+    {
+        // find the best loop of at least 30 frames (matching poses and velocities)
+        let settings = LoopSettings { min_length: 30, max_length: None, velocity_weight: 0.01 };
+        if let Some(best) = bvh_data.find_loop(&bvh_metadata, &settings) {
+            // cut it out and blend the seam, the last frame is the first pose again
+            let (loop_metadata, loop_data) = bvh_data.make_loop(&bvh_metadata, best.start, best.end, 10);
+            // play it 3 times, cycles share their seam frames
+            let (_repeated_metadata, _repeated_data) = loop_data.repeat_loop(&loop_metadata, 3);
+        }
+    }
 
     //////////////////////////////// visualize skeleton ////////////////
     // with "visualize" feature enabled you can visualize the skeleton in a bevy app
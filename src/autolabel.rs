@@ -0,0 +1,208 @@
+use crate::humanoid::HumanoidBone;
+use crate::types::*;
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A joint labeled as a canonical humanoid bone, with a confidence in range 0.0 - 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoneAssignment {
+    pub bone: HumanoidBone,
+    pub joint: Index,
+    pub confidence: f64,
+}
+
+/// Result of the topology-based humanoid detection.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HumanoidDetection {
+    pub assignments: Vec<BoneAssignment>,
+}
+
+impl HumanoidDetection {
+    /// Joint index of a canonical bone.
+    pub fn get(&self, bone: HumanoidBone) -> Option<Index> {
+        self.assignment(bone).map(|a| a.joint)
+    }
+
+    /// Full assignment (joint + confidence) of a canonical bone.
+    pub fn assignment(&self, bone: HumanoidBone) -> Option<&BoneAssignment> {
+        self.assignments.iter().find(|a| a.bone == bone)
+    }
+
+    fn assign(&mut self, bone: HumanoidBone, joint: Index, confidence: f64) {
+        self.assignments.push(BoneAssignment {
+            bone,
+            joint,
+            confidence: confidence.clamp(0.0, 1.0),
+        });
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Follow single-child joints starting at `start`. Ends at the first branching joint or leaf (both included).
+fn __follow_chain(metadata: &BvhMetadata, start: Index) -> Vec<Index> {
+    let mut chain = vec![start];
+    let mut current = start;
+    while metadata.joints[current].children.len() == 1 {
+        current = metadata.joints[current].children[0];
+        chain.push(current);
+    }
+    chain
+}
+
+/// Similarity (0.0 - 1.0) of two subtrees based on their joint counts and chain lengths.
+fn __subtree_similarity(metadata: &BvhMetadata, a: Index, b: Index) -> f64 {
+    let ratio = |x: usize, y: usize| x.min(y) as f64 / x.max(y).max(1) as f64;
+    let size = ratio(metadata.get_subtree(a).len(), metadata.get_subtree(b).len());
+    let chain = ratio(__follow_chain(metadata, a).len(), __follow_chain(metadata, b).len());
+    size * chain
+}
+
+/// Out of the children of a branching joint, find the most similar (symmetric) pair and the remaining "middle" child.
+/// Returns (pair, middle, similarity of the pair).
+fn __split_symmetric_pair(metadata: &BvhMetadata, children: &[Index]) -> Option<((Index, Index), Option<Index>, f64)> {
+    let mut best: Option<((Index, Index), f64)> = None;
+    for (i, &a) in children.iter().enumerate() {
+        for &b in children.iter().skip(i + 1) {
+            let similarity = __subtree_similarity(metadata, a, b);
+            if !best.is_some_and(|(_, s)| similarity <= s) {
+                best = Some(((a, b), similarity));
+            }
+        }
+    }
+    let ((a, b), similarity) = best?;
+    // the middle one is the biggest remaining subtree (e.g. spine rather than a tail)
+    let middle = children
+        .iter()
+        .copied()
+        .filter(|&c| c != a && c != b)
+        .max_by_key(|&c| metadata.get_subtree(c).len());
+    Some(((a, b), middle, similarity))
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Label humanoid bones from the skeleton topology (children, symmetric subtrees) and geometry, without looking at joint names.
+    ///
+    /// Geometry (which of the symmetric limbs is left and which is right) is taken from the pose at `frame`,
+    /// or from the rest pose if `None`. Prefer a frame for files whose rest pose isn't a sensible pose (e.g. all offsets along one axis).
+    pub fn detect_humanoid(&self, metadata: &BvhMetadata, frame: Option<usize>) -> HumanoidDetection {
+        let positions: Vec<Position> = match frame {
+            Some(frame) => self.pose_global_positions.iter().map(|track| track[frame]).collect(),
+            None => self.rest_global_positions.clone(),
+        };
+        let mut detection = HumanoidDetection::default();
+
+        //// hips: first joint (from the root) where the hierarchy branches
+        let hips_chain = __follow_chain(metadata, 0);
+        let hips = *hips_chain.last().unwrap();
+        let hips_children = &metadata.joints[hips].children;
+        if hips_children.len() < 3 {
+            return detection;
+        }
+        detection.assign(HumanoidBone::Hips, hips, if hips_children.len() == 3 { 1.0 } else { 0.7 });
+
+        //// legs are the most symmetric pair of the hips' children, spine is what's left
+        let Some(((leg_a, leg_b), Some(spine_root), leg_similarity)) = __split_symmetric_pair(metadata, hips_children) else {
+            return detection;
+        };
+
+        //// spine: chain from hips up to the chest branch (UpperChest)
+        let spine_chain = __follow_chain(metadata, spine_root);
+        let chest_branch = *spine_chain.last().unwrap();
+        let spine_confidence = if spine_chain.len() == 3 { 1.0 } else { 0.8 };
+        detection.assign(HumanoidBone::Spine, spine_chain[0], spine_confidence);
+        if spine_chain.len() >= 3 {
+            detection.assign(HumanoidBone::Chest, spine_chain[spine_chain.len() - 2], spine_confidence * 0.9);
+        }
+        if spine_chain.len() >= 2 {
+            detection.assign(HumanoidBone::UpperChest, chest_branch, spine_confidence);
+        }
+
+        //// arms are the most symmetric pair of the chest's children, neck is what's left
+        let chest_children = &metadata.joints[chest_branch].children;
+        let arms = __split_symmetric_pair(metadata, chest_children);
+        if let Some((_, Some(neck_root), arm_similarity)) = arms {
+            let neck_chain = __follow_chain(metadata, neck_root);
+            detection.assign(HumanoidBone::Neck, neck_chain[0], arm_similarity);
+            if neck_chain.len() >= 2 {
+                detection.assign(HumanoidBone::Head, *neck_chain.last().unwrap(), arm_similarity);
+            }
+        }
+
+        //// left/right from geometry: right = forward x up, forward from feet (heel -> toes)
+        let up = positions[chest_branch] - positions[hips];
+        let leg_chains = [__follow_chain(metadata, leg_a), __follow_chain(metadata, leg_b)];
+        let mut forward = Position::identity();
+        for chain in leg_chains.iter().filter(|chain| chain.len() >= 4) {
+            forward += positions[chain[chain.len() - 1]] - positions[chain[chain.len() - 2]];
+        }
+        forward -= up * (forward.dot(up) / up.magnitude2().max(1e-12));
+        if forward.magnitude2() < 1e-12 {
+            // no toes - assume the conventional facing direction (+Z)
+            forward = Position::unit_z();
+        }
+        let right = forward.cross(up).normalize();
+
+        // (right chain, left chain, side confidence)
+        let sides = |a: Vec<Index>, b: Vec<Index>| {
+            let across = (positions[a[0]] - positions[b[0]]).dot(right) / (positions[a[0]] - positions[b[0]]).magnitude().max(1e-12);
+            // the further the pair is spread along the right axis, the more certain the sides
+            let confidence = 0.5 + across.abs() / 2.0;
+            if across >= 0.0 {
+                (a, b, confidence)
+            } else {
+                (b, a, confidence)
+            }
+        };
+
+        //// legs: [UpperLeg (after optional hip joints), ..., LowerLeg, Foot, Toes]
+        let [leg_a, leg_b] = leg_chains;
+        let (right_leg, left_leg, leg_side) = sides(leg_a, leg_b);
+        for (chain, bones) in [
+            (right_leg, [HumanoidBone::RightUpperLeg, HumanoidBone::RightLowerLeg, HumanoidBone::RightFoot, HumanoidBone::RightToes]),
+            (left_leg, [HumanoidBone::LeftUpperLeg, HumanoidBone::LeftLowerLeg, HumanoidBone::LeftFoot, HumanoidBone::LeftToes]),
+        ] {
+            let structure = match chain.len() {
+                4 => 1.0,
+                3 | 5 => 0.8,
+                _ => 0.5,
+            };
+            let confidence = structure * leg_similarity * leg_side;
+            let joints: Vec<Index> = if chain.len() >= 4 {
+                chain[chain.len() - 4..].to_vec()
+            } else {
+                chain.clone()
+            };
+            for (bone, joint) in bones.iter().zip(joints) {
+                detection.assign(*bone, joint, confidence);
+            }
+        }
+
+        //// arms: [Shoulder, UpperArm, ..., LowerArm, Hand]
+        if let Some(((arm_a, arm_b), _, arm_similarity)) = arms {
+            let (right_arm, left_arm, arm_side) = sides(__follow_chain(metadata, arm_a), __follow_chain(metadata, arm_b));
+            for (chain, bones) in [
+                (right_arm, [HumanoidBone::RightShoulder, HumanoidBone::RightUpperArm, HumanoidBone::RightLowerArm, HumanoidBone::RightHand]),
+                (left_arm, [HumanoidBone::LeftShoulder, HumanoidBone::LeftUpperArm, HumanoidBone::LeftLowerArm, HumanoidBone::LeftHand]),
+            ] {
+                let structure = if chain.len() == 4 { 1.0 } else { 0.7 };
+                let confidence = structure * arm_similarity * arm_side;
+                let n = chain.len();
+                let joints: Vec<(HumanoidBone, Index)> = match n {
+                    0..=2 => Vec::new(),
+                    // no shoulder (clavicle) joint
+                    3 => bones[1..].iter().copied().zip(chain.iter().copied()).collect(),
+                    _ => vec![(bones[0], chain[0]), (bones[1], chain[1]), (bones[2], chain[n - 2]), (bones[3], chain[n - 1])],
+                };
+                for (bone, joint) in joints {
+                    detection.assign(bone, joint, confidence);
+                }
+            }
+        }
+
+        detection
+    }
+}
@@ -0,0 +1,266 @@
+use crate::clip::{RootAlignment, __align_root, __assert_compatible};
+use crate::mask::JointMask;
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// How to interpolate local rotations when blending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationBlend {
+    /// Spherical linear interpolation (constant angular velocity).
+    Slerp,
+    /// Normalized linear interpolation (cheaper, slightly uneven angular velocity).
+    Nlerp,
+}
+
+impl RotationBlend {
+    pub(crate) fn blend(self, a: Quaternion, b: Quaternion, weight: f64) -> Quaternion {
+        match self {
+            RotationBlend::Slerp => a.slerp(b, weight),
+            RotationBlend::Nlerp => a.nlerp(b, weight),
+        }
+    }
+}
+
+/// Smooth ease-in/ease-out weight curve used for transitions.
+pub(crate) fn __smoothstep(t: f64) -> f64 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Difference between an animation and a reference pose, which can be added on top of other animations (see `BvhData::make_additive`).
+#[derive(Debug, Clone)]
+pub struct AdditiveLayer {
+    pub root_position_deltas: Vec<Position>,
+    pub local_rotation_deltas: Vec<Vec<Quaternion>>, // [joint][frame]
+}
+
+impl AdditiveLayer {
+    pub fn num_frames(&self) -> usize {
+        self.root_position_deltas.len()
+    }
+}
+
+/// Blend `a` with `b` with weight `frame_weight * joint_weight` (0 = only `a`, 1 = only `b`). The result has `frame_weights.len()` frames.
+fn __blend(
+    metadata_a: &BvhMetadata,
+    a: &BvhData,
+    metadata_b: &BvhMetadata,
+    b: &BvhData,
+    frame_weights: &[f64],
+    mask: &JointMask,
+    method: RotationBlend,
+) -> (BvhMetadata, BvhData) {
+    __assert_compatible(metadata_a, metadata_b);
+    assert!(
+        frame_weights.len() <= metadata_a.num_frames && frame_weights.len() <= metadata_b.num_frames,
+        "More blend weights ({}) than frames in one of the animations!",
+        frame_weights.len()
+    );
+
+    let root_positions = frame_weights
+        .iter()
+        .enumerate()
+        .map(|(frame, &w)| {
+            let p_a = a.pose_global_positions[0][frame];
+            let p_b = b.pose_global_positions[0][frame];
+            p_a + (p_b - p_a) * (w * mask.weight(0))
+        })
+        .collect();
+    let local_rotations = a
+        .pose_local_rotations
+        .iter()
+        .zip(b.pose_local_rotations.iter())
+        .enumerate()
+        .map(|(joint_index, (track_a, track_b))| {
+            let joint_weight = mask.weight(joint_index);
+            frame_weights
+                .iter()
+                .enumerate()
+                .map(|(frame, &w)| method.blend(track_a[frame], track_b[frame], w * joint_weight))
+                .collect()
+        })
+        .collect();
+
+    let new_metadata = BvhMetadata {
+        num_frames: frame_weights.len(),
+        ..metadata_a.clone()
+    };
+    let new_data = __build_bvh_data(&new_metadata, a, root_positions, local_rotations);
+    (new_metadata, new_data)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Blend this animation with `other` (same skeleton) using a constant weight (0 = only `self`, 1 = only `other`).
+    /// The result is as long as the shorter of the two animations.
+    pub fn blend(
+        &self,
+        metadata: &BvhMetadata,
+        other: &BvhData,
+        other_metadata: &BvhMetadata,
+        weight: f64,
+        method: RotationBlend,
+    ) -> (BvhMetadata, BvhData) {
+        let num_frames = metadata.num_frames.min(other_metadata.num_frames);
+        self.blend_per_frame(metadata, other, other_metadata, &vec![weight; num_frames], method)
+    }
+
+    /// Blend this animation with `other` (same skeleton) using a weight per frame (0 = only `self`, 1 = only `other`).
+    /// The result has `weights.len()` frames.
+    ///
+    /// Local rotations are slerped/nlerped and root positions are lerped. Global pose is then recalculated with forward kinematics.
+    pub fn blend_per_frame(
+        &self,
+        metadata: &BvhMetadata,
+        other: &BvhData,
+        other_metadata: &BvhMetadata,
+        weights: &[f64],
+        method: RotationBlend,
+    ) -> (BvhMetadata, BvhData) {
+        let mask = JointMask::uniform(metadata, 1.0);
+        __blend(metadata, self, other_metadata, other, weights, &mask, method)
+    }
+
+    /// Blend this animation with `other` (same skeleton) using a weight per joint (0 = only `self`, 1 = only `other`),
+    /// e.g. upper body from a waving clip on top of a walk with `JointMask::subtree(&metadata, "Spine2")`.
+    /// The result is as long as the shorter of the two animations.
+    pub fn blend_masked(
+        &self,
+        metadata: &BvhMetadata,
+        other: &BvhData,
+        other_metadata: &BvhMetadata,
+        mask: &JointMask,
+        method: RotationBlend,
+    ) -> (BvhMetadata, BvhData) {
+        let num_frames = metadata.num_frames.min(other_metadata.num_frames);
+        __blend(metadata, self, other_metadata, other, &vec![1.0; num_frames], mask, method)
+    }
+
+    /// Make an additive layer out of this animation: per-frame difference between the animation and a `reference` pose (e.g. `data.pose_at_frame(0)`).
+    pub fn make_additive(&self, metadata: &BvhMetadata, reference: &Pose) -> AdditiveLayer {
+        AdditiveLayer {
+            root_position_deltas: self.pose_global_positions[0]
+                .iter()
+                .take(metadata.num_frames)
+                .map(|&p| p - reference.root_position)
+                .collect(),
+            local_rotation_deltas: self
+                .pose_local_rotations
+                .iter()
+                .zip(reference.local_rotations.iter())
+                .map(|(track, &reference_rotation)| {
+                    let reference_inv = reference_rotation.conjugate();
+                    track.iter().map(|&q| reference_inv * q).collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Add an additive layer on top of this animation (`local_rotation * delta^(weight * mask)`, `root_position + delta * weight * mask`).
+    /// The result is as long as the shorter of the animation and the layer.
+    pub fn apply_additive(
+        &self,
+        metadata: &BvhMetadata,
+        layer: &AdditiveLayer,
+        weight: f64,
+        mask: &JointMask,
+    ) -> (BvhMetadata, BvhData) {
+        let num_frames = metadata.num_frames.min(layer.num_frames());
+        let root_weight = weight * mask.weight(0);
+        let root_positions = (0..num_frames)
+            .map(|frame| self.pose_global_positions[0][frame] + layer.root_position_deltas[frame] * root_weight)
+            .collect();
+        let local_rotations = self
+            .pose_local_rotations
+            .iter()
+            .zip(layer.local_rotation_deltas.iter())
+            .enumerate()
+            .map(|(joint_index, (track, deltas))| {
+                let w = weight * mask.weight(joint_index);
+                (0..num_frames)
+                    .map(|frame| track[frame] * Quaternion::identity().slerp(deltas[frame], w))
+                    .collect()
+            })
+            .collect();
+
+        let new_metadata = BvhMetadata {
+            num_frames,
+            ..metadata.clone()
+        };
+        let new_data = __build_bvh_data(&new_metadata, self, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+
+    /// Transition from this animation into `other` (same skeleton) over `transition_frames` frames.
+    ///
+    /// The last `transition_frames` frames of `self` are blended (with a smooth ease-in/ease-out curve) with the first `transition_frames` frames of `other`,
+    /// so the result has `num_frames + other_num_frames - transition_frames` frames.
+    /// `alignment` places the root of `other` where `self` is at the start of the transition.
+    pub fn crossfade(
+        &self,
+        metadata: &BvhMetadata,
+        other: &BvhData,
+        other_metadata: &BvhMetadata,
+        transition_frames: usize,
+        method: RotationBlend,
+        alignment: RootAlignment,
+    ) -> (BvhMetadata, BvhData) {
+        __assert_compatible(metadata, other_metadata);
+        let num_frames_a = metadata.num_frames;
+        let num_frames_b = other_metadata.num_frames;
+        let transition_frames = transition_frames.min(num_frames_a).min(num_frames_b);
+        let start = num_frames_a - transition_frames;
+        if num_frames_a == 0 || num_frames_b == 0 {
+            return self.concat(metadata, other, other_metadata, RootAlignment::None);
+        }
+
+        let (other_root_positions, other_root_rotations) = __align_root(
+            other,
+            self.pose_global_positions[0][start],
+            self.pose_local_rotations[0][start],
+            alignment,
+        );
+        let weight = |k: usize| __smoothstep((k + 1) as f64 / (transition_frames + 1) as f64);
+
+        //// root positions
+        let mut root_positions = self.pose_global_positions[0][..start].to_vec();
+        for (k, (&a, &b)) in self.pose_global_positions[0][start..]
+            .iter()
+            .zip(other_root_positions.iter())
+            .enumerate()
+        {
+            root_positions.push(a + (b - a) * weight(k));
+        }
+        root_positions.extend_from_slice(&other_root_positions[transition_frames..]);
+
+        //// local rotations
+        let local_rotations = self
+            .pose_local_rotations
+            .iter()
+            .enumerate()
+            .map(|(joint_index, track)| {
+                let other_track = if joint_index == 0 {
+                    &other_root_rotations
+                } else {
+                    &other.pose_local_rotations[joint_index]
+                };
+                let mut new_track = track[..start].to_vec();
+                for k in 0..transition_frames {
+                    new_track.push(method.blend(track[start + k], other_track[k], weight(k)));
+                }
+                new_track.extend_from_slice(&other_track[transition_frames..]);
+                new_track
+            })
+            .collect();
+
+        let new_metadata = BvhMetadata {
+            num_frames: num_frames_a + num_frames_b - transition_frames,
+            ..metadata.clone()
+        };
+        let new_data = __build_bvh_data(&new_metadata, self, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+}
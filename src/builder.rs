@@ -0,0 +1,218 @@
+use crate::parse::{__build_bvh_data, __build_bvh_data_with_rest};
+use crate::skeleton::__rebuild_hierarchy;
+use crate::types::*;
+use crate::utils;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Build a skeleton in code instead of parsing the HIERARCHY of a .bvh file.
+/// Rest pose is calculated the same way as in `parse_bvh`. Joints can be added in any order: the finished skeleton orders them
+/// depth-first like a .bvh file does (so joint indices and the order of frame values follow the hierarchy, not the order of `joint` calls).
+#[derive(Debug, Clone)]
+pub struct SkeletonBuilder {
+    joints: Vec<Joint>,
+    offsets: Vec<Position>,
+    /// Order of the rotation channels of every joint (e.g. "ZXY"), empty if the joint has no rotation channels.
+    rotation_orders: Vec<String>,
+}
+
+impl SkeletonBuilder {
+    /// Start a skeleton with its root joint (which has position and rotation channels).
+    pub fn new(root_name: &str, root_offset: Position) -> SkeletonBuilder {
+        SkeletonBuilder {
+            joints: vec![Joint {
+                name: root_name.to_string(),
+                index: 0,
+                parent_index: -1,
+                depth: 0,
+                children: Vec::new(),
+                is_leaf: false,
+                endsite: None,
+            }],
+            offsets: vec![root_offset],
+            rotation_orders: vec!["ZXY".to_string()],
+        }
+    }
+
+    fn __index(&self, name: &str) -> Index {
+        self.joints
+            .iter()
+            .position(|joint| joint.name == name)
+            .unwrap_or_else(|| panic!("Joint {} not found", name))
+    }
+
+    /// Add a joint (with "ZXY" rotation channels) as the last child of `parent`.
+    pub fn joint(mut self, name: &str, parent: &str, offset: Position) -> SkeletonBuilder {
+        assert!(
+            self.joints.iter().all(|joint| joint.name != name),
+            "Joint {} already exists!",
+            name
+        );
+        let parent = self.__index(parent);
+        assert!(self.joints[parent].endsite.is_none(), "Joint with an endsite can't have children!");
+        let index = self.joints.len();
+        self.joints[parent].children.push(index);
+        self.joints.push(Joint {
+            name: name.to_string(),
+            index,
+            parent_index: parent as ParentIndex,
+            depth: self.joints[parent].depth + 1,
+            children: Vec::new(),
+            is_leaf: false,
+            endsite: None,
+        });
+        self.offsets.push(offset);
+        self.rotation_orders.push("ZXY".to_string());
+        self
+    }
+
+    /// Set the order of the rotation channels of a joint (e.g. "XYZ"), or "" for a joint without rotation channels.
+    pub fn channels(mut self, name: &str, rotation_order: &str) -> SkeletonBuilder {
+        assert!(
+            rotation_order.is_empty() || ["XYZ", "XZY", "YXZ", "YZX", "ZXY", "ZYX"].contains(&rotation_order),
+            "Invalid euler angles order!"
+        );
+        let index = self.__index(name);
+        self.rotation_orders[index] = rotation_order.to_string();
+        self
+    }
+
+    /// Add an endsite to a joint without children.
+    pub fn endsite(mut self, name: &str, offset: Position) -> SkeletonBuilder {
+        let index = self.__index(name);
+        assert!(self.joints[index].children.is_empty(), "Joint with children can't have an endsite!");
+        self.joints[index].endsite = Some(Endsite { offset });
+        self.joints[index].is_leaf = true;
+        self
+    }
+
+    /// Metadata of the skeleton with joints in depth-first order, and the index of every new joint in `joints`.
+    fn __metadata(&self, num_frames: usize, frame_time: f64) -> (BvhMetadata, Vec<Index>) {
+        for joint in self.joints.iter() {
+            assert!(
+                !joint.children.is_empty() || joint.endsite.is_some(),
+                "Joint {} has no children and no endsite!",
+                joint.name
+            );
+        }
+        let (joints, order) = __rebuild_hierarchy(&self.joints);
+        let metadata = BvhMetadata {
+            joints,
+            num_frames,
+            frame_time,
+            fps: (1.0 / frame_time).round() as u32,
+        };
+        (metadata, order)
+    }
+
+    /// Build the skeleton without any frames (rest pose only). Frame time defaults to 1/30 s.
+    pub fn build(self) -> (BvhMetadata, BvhData) {
+        self.animation(1.0 / 30.0).build()
+    }
+
+    /// Continue with animating the skeleton.
+    pub fn animation(self, frame_time: f64) -> AnimationBuilder {
+        let (metadata, order) = self.__metadata(0, frame_time);
+        let num_joints = metadata.joints.len();
+        let offsets = order.iter().map(|&old| self.offsets[old]).collect();
+        let data = __build_bvh_data_with_rest(&metadata, offsets, Vec::new(), vec![Vec::new(); num_joints]);
+        AnimationBuilder {
+            metadata,
+            data,
+            rotation_orders: order.iter().map(|&old| self.rotation_orders[old].clone()).collect(),
+            root_positions: Vec::new(),
+            local_rotations: vec![Vec::new(); num_joints],
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Build an animation frame by frame (like the MOTION section of a .bvh file).
+#[derive(Debug, Clone)]
+pub struct AnimationBuilder {
+    metadata: BvhMetadata,
+    data: BvhData,
+    rotation_orders: Vec<String>,
+    root_positions: Vec<Position>,
+    /// `[joint][frame]`
+    local_rotations: Vec<Vec<Quaternion>>,
+}
+
+impl AnimationBuilder {
+    /// Animate an existing skeleton (its frames are ignored). All joints get "ZXY" rotation channels (the most common order in .bvh files),
+    /// which only matters for `push_frame_euler`; change them with `channels`.
+    pub fn new(metadata: &BvhMetadata, data: &BvhData, frame_time: f64) -> AnimationBuilder {
+        let num_joints = metadata.joints.len();
+        let metadata = BvhMetadata {
+            num_frames: 0,
+            frame_time,
+            fps: (1.0 / frame_time).round() as u32,
+            ..metadata.clone()
+        };
+        let data = __build_bvh_data_with_rest(&metadata, data.rest_local_positions.clone(), Vec::new(), vec![Vec::new(); num_joints]);
+        AnimationBuilder {
+            metadata,
+            data,
+            rotation_orders: vec!["ZXY".to_string(); num_joints],
+            root_positions: Vec::new(),
+            local_rotations: vec![Vec::new(); num_joints],
+        }
+    }
+
+    /// Set the order of the rotation channels of a joint (e.g. "XYZ"), or "" for a joint without rotation channels.
+    pub fn channels(mut self, name: &str, rotation_order: &str) -> AnimationBuilder {
+        assert!(
+            rotation_order.is_empty() || ["XYZ", "XZY", "YXZ", "YZX", "ZXY", "ZYX"].contains(&rotation_order),
+            "Invalid euler angles order!"
+        );
+        let index = self.metadata.find_joint_by_name(name).index;
+        self.rotation_orders[index] = rotation_order.to_string();
+        self
+    }
+
+    /// Push a frame of root translation and local rotations (one per joint, in the order of the skeleton's joints).
+    pub fn push_frame(mut self, root_position: Position, local_rotations: &[Quaternion]) -> AnimationBuilder {
+        assert_eq!(
+            local_rotations.len(),
+            self.metadata.joints.len(),
+            "Frame has different number of rotations than the skeleton has joints!"
+        );
+        self.root_positions.push(root_position);
+        for (track, &q) in self.local_rotations.iter_mut().zip(local_rotations) {
+            track.push(q);
+        }
+        self
+    }
+
+    /// Push a frame of euler angles in DEGREES, laid out like a MOTION line without the root position:
+    /// 3 values for every joint with rotation channels, in the joint's channel order.
+    pub fn push_frame_euler(self, root_position: Position, channels: &[f64]) -> AnimationBuilder {
+        let num_channels: usize = self.rotation_orders.iter().filter(|order| !order.is_empty()).count() * 3;
+        assert_eq!(channels.len(), num_channels, "Frame has wrong number of channels!");
+        let mut values = channels.iter();
+        let local_rotations: Vec<Quaternion> = self
+            .rotation_orders
+            .iter()
+            .map(|order| {
+                if order.is_empty() {
+                    return Quaternion::identity();
+                }
+                let (e1, e2, e3) = (*values.next().unwrap(), *values.next().unwrap(), *values.next().unwrap());
+                let eul = utils::__reorder_vector(e1, e2, e3, order);
+                utils::__from_euler_to_quat(eul.0, eul.1, eul.2, order)
+            })
+            .collect();
+        self.push_frame(root_position, &local_rotations)
+    }
+
+    /// Calculate the global pose (forward kinematics) of all frames, the same way as in `parse_bvh`.
+    pub fn build(self) -> (BvhMetadata, BvhData) {
+        let metadata = BvhMetadata {
+            num_frames: self.root_positions.len(),
+            ..self.metadata
+        };
+        let data = __build_bvh_data(&metadata, &self.data, self.root_positions, self.local_rotations);
+        (metadata, data)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use crate::utils;
+use std::ops::Range;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// How to place the root of the appended clip when concatenating two animations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootAlignment {
+    /// Keep root translations and rotations as they are.
+    None,
+    /// Translate the second clip on the ground plane (XZ) so that its first frame is one root step (of the last frame of
+    /// the first clip) after the end of the first clip, so the root keeps its velocity across the seam.
+    Position,
+    /// Like `Position`, but additionally rotate the second clip around the Y axis so that it continues in the heading the
+    /// first clip ends with, advanced by the turn of its last frame.
+    PositionAndHeading,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Copy the given frames (in the given order) into new metadata and data.
+pub(crate) fn __pick_frames(metadata: &BvhMetadata, data: &BvhData, frames: &[usize]) -> (BvhMetadata, BvhData) {
+    let pick_positions = |tracks: &Vec<Vec<Position>>| -> Vec<Vec<Position>> {
+        tracks.iter().map(|track| frames.iter().map(|&f| track[f]).collect()).collect()
+    };
+    let pick_rotations = |tracks: &Vec<Vec<Quaternion>>| -> Vec<Vec<Quaternion>> {
+        tracks.iter().map(|track| frames.iter().map(|&f| track[f]).collect()).collect()
+    };
+
+    let new_metadata = BvhMetadata {
+        num_frames: frames.len(),
+        ..metadata.clone()
+    };
+    let new_data = BvhData {
+        rest_local_positions: data.rest_local_positions.clone(),
+        rest_local_rotations: data.rest_local_rotations.clone(),
+        rest_global_positions: data.rest_global_positions.clone(),
+        rest_global_rotations: data.rest_global_rotations.clone(),
+        pose_global_positions: pick_positions(&data.pose_global_positions),
+        pose_global_rotations: pick_rotations(&data.pose_global_rotations),
+        pose_local_rotations: pick_rotations(&data.pose_local_rotations),
+        pose_local_positions: pick_positions(&data.pose_local_positions),
+    };
+    (new_metadata, new_data)
+}
+
+/// Panic if the two animations don't share the same skeleton and frame time.
+pub(crate) fn __assert_compatible(metadata: &BvhMetadata, other_metadata: &BvhMetadata) {
+    assert_eq!(
+        metadata.joints.len(),
+        other_metadata.joints.len(),
+        "Animations have different number of joints!"
+    );
+    for (a, b) in metadata.joints.iter().zip(other_metadata.joints.iter()) {
+        assert!(
+            a.name == b.name && a.parent_index == b.parent_index,
+            "Animations have different skeletons (joint {} vs {})!",
+            a.name,
+            b.name
+        );
+    }
+    assert!(
+        (metadata.frame_time - other_metadata.frame_time).abs() < 1e-6,
+        "Animations have different frame times ({} vs {}). Resample one of them first.",
+        metadata.frame_time,
+        other_metadata.frame_time
+    );
+}
+
+/// Root positions and rotations of `data` moved so that its first frame is placed at `target_position` (on the ground plane)
+/// and, depending on `alignment`, rotated around the Y axis so that it faces the heading of `target_rotation`.
+pub(crate) fn __align_root(
+    data: &BvhData,
+    target_position: Position,
+    target_rotation: Quaternion,
+    alignment: RootAlignment,
+) -> (Vec<Position>, Vec<Quaternion>) {
+    if alignment == RootAlignment::None || data.pose_global_positions[0].is_empty() {
+        return (data.pose_global_positions[0].clone(), data.pose_local_rotations[0].clone());
+    }
+    let start_position = data.pose_global_positions[0][0];
+    let heading = if alignment == RootAlignment::PositionAndHeading {
+        let start_rotation = data.pose_local_rotations[0][0];
+        utils::__twist_about_axis(target_rotation * start_rotation.conjugate(), Position::new(0.0, 1.0, 0.0))
+    } else {
+        Quaternion::identity()
+    };
+
+    let positions = data.pose_global_positions[0]
+        .iter()
+        .map(|&p| {
+            let p = heading * (p - start_position);
+            Position::new(target_position.x + p.x, start_position.y + p.y, target_position.z + p.z)
+        })
+        .collect();
+    let rotations = data.pose_local_rotations[0].iter().map(|&q| heading * q).collect();
+    (positions, rotations)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Cut out a range of frames.
+    pub fn slice(&self, metadata: &BvhMetadata, frames: Range<usize>) -> (BvhMetadata, BvhData) {
+        assert!(
+            frames.start <= frames.end && frames.end <= metadata.num_frames,
+            "Frame range {:?} out of bounds (animation has {} frames)!",
+            frames,
+            metadata.num_frames
+        );
+        __pick_frames(metadata, self, &frames.collect::<Vec<_>>())
+    }
+
+    /// Play the animation backwards.
+    pub fn reverse(&self, metadata: &BvhMetadata) -> (BvhMetadata, BvhData) {
+        __pick_frames(metadata, self, &(0..metadata.num_frames).rev().collect::<Vec<_>>())
+    }
+
+    /// Append `other` animation (sharing the same skeleton and frame time) after this one.
+    pub fn concat(
+        &self,
+        metadata: &BvhMetadata,
+        other: &BvhData,
+        other_metadata: &BvhMetadata,
+        alignment: RootAlignment,
+    ) -> (BvhMetadata, BvhData) {
+        __assert_compatible(metadata, other_metadata);
+        if metadata.num_frames == 0 || other_metadata.num_frames == 0 || alignment == RootAlignment::None {
+            let frames: Vec<usize> = (0..metadata.num_frames).collect();
+            let (mut new_metadata, mut new_data) = __pick_frames(metadata, self, &frames);
+            new_metadata.num_frames += other_metadata.num_frames;
+            for (track, other_track) in new_data
+                .pose_global_positions
+                .iter_mut()
+                .zip(other.pose_global_positions.iter())
+                .chain(new_data.pose_local_positions.iter_mut().zip(other.pose_local_positions.iter()))
+            {
+                track.extend_from_slice(other_track);
+            }
+            for (track, other_track) in new_data
+                .pose_global_rotations
+                .iter_mut()
+                .zip(other.pose_global_rotations.iter())
+                .chain(new_data.pose_local_rotations.iter_mut().zip(other.pose_local_rotations.iter()))
+            {
+                track.extend_from_slice(other_track);
+            }
+            return (new_metadata, new_data);
+        }
+
+        //// align the root of the second clip one frame step after the last frame of the first clip
+        let last = metadata.num_frames - 1;
+        let last_position = self.pose_global_positions[0][last];
+        let last_rotation = self.pose_local_rotations[0][last];
+        let (step, turn) = if last > 0 {
+            let previous_rotation = self.pose_local_rotations[0][last - 1];
+            (
+                last_position - self.pose_global_positions[0][last - 1],
+                utils::__twist_about_axis(last_rotation * previous_rotation.conjugate(), Position::new(0.0, 1.0, 0.0)),
+            )
+        } else {
+            (Position::new(0.0, 0.0, 0.0), Quaternion::identity())
+        };
+        let (aligned_positions, aligned_rotations) =
+            __align_root(other, last_position + step, turn * last_rotation, alignment);
+        let mut root_positions = self.pose_global_positions[0].clone();
+        root_positions.extend(aligned_positions);
+
+        let mut local_rotations = self.pose_local_rotations.clone();
+        for (joint_index, track) in local_rotations.iter_mut().enumerate() {
+            if joint_index == 0 {
+                track.extend_from_slice(&aligned_rotations);
+            } else {
+                track.extend_from_slice(&other.pose_local_rotations[joint_index]);
+            }
+        }
+
+        let new_metadata = BvhMetadata {
+            num_frames: metadata.num_frames + other_metadata.num_frames,
+            ..metadata.clone()
+        };
+        let new_data = __build_bvh_data(&new_metadata, self, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+
+    /// Repeat the animation `times` times (e.g. a locomotion cycle). See `concat` for root alignment.
+    pub fn repeat(&self, metadata: &BvhMetadata, times: usize, alignment: RootAlignment) -> (BvhMetadata, BvhData) {
+        if times == 0 {
+            return self.slice(metadata, 0..0);
+        }
+        let (mut new_metadata, mut new_data) = self.slice(metadata, 0..metadata.num_frames);
+        for _ in 1..times {
+            (new_metadata, new_data) = new_data.concat(&new_metadata, self, metadata, alignment);
+        }
+        (new_metadata, new_data)
+    }
+}
@@ -0,0 +1,365 @@
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use crate::utils::__same_hemisphere;
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Error tolerances of keyframe reduction, measured in global space (after forward kinematics).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionSettings {
+    /// Maximal error of joint (and endsite) positions, in the units of the skeleton.
+    pub max_position_error: f64,
+    /// Maximal error of global joint rotations, in radians.
+    pub max_angle_error: f64,
+    /// Quantize rotation keys to the given number of bits per component (smallest three encoding, 2..=20 bits).
+    pub quantization_bits: Option<u8>,
+}
+
+/// Keys of a track: values at the given (increasing) frames, linearly interpolated in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyframeTrack<T> {
+    pub frames: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+/// Rotation keys, either full quaternions or quantized (see `CompressionSettings::quantization_bits`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RotationTrack {
+    Full(KeyframeTrack<Quaternion>),
+    Quantized { bits: u8, keys: KeyframeTrack<u64> },
+}
+
+/// Keyframe-reduced animation. Decompress with `decompress`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedAnimation {
+    pub num_frames: usize,
+    pub frame_time: f64,
+    pub root_positions: KeyframeTrack<Position>,
+    /// Local rotations of every joint.
+    pub rotations: Vec<RotationTrack>,
+}
+
+/// Result of a compression. Sizes assume 32-bit floats and frame indices of the keys as small as the number of frames allows
+/// (1 byte up to 256 frames, 2 bytes up to 65536 frames, ...).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionReport {
+    pub original_keys: usize,
+    pub compressed_keys: usize,
+    pub original_bytes: usize,
+    pub compressed_bytes: usize,
+    /// `original_bytes / compressed_bytes`
+    pub compression_ratio: f64,
+    /// Maximal error of global joint positions of the decompressed animation.
+    pub max_position_error: f64,
+    /// Maximal error of global joint rotations of the decompressed animation, in radians.
+    pub max_angle_error: f64,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Quantize a rotation with the smallest three encoding: index of the largest component (2 bits)
+/// followed by the other three components (`bits` each). The largest component is restored from the unit length.
+fn __quantize(q: Quaternion, bits: u8) -> u64 {
+    let components = [q.s, q.v.x, q.v.y, q.v.z];
+    let largest = (0..4).max_by(|&a, &b| components[a].abs().total_cmp(&components[b].abs())).unwrap();
+    let sign = components[largest].signum();
+    let max_value = (1u64 << bits) - 1;
+    let mut packed = largest as u64;
+    for i in (0..4).filter(|&i| i != largest) {
+        // the other components are within +-1/sqrt(2)
+        let t = ((components[i] * sign * std::f64::consts::SQRT_2 + 1.0) / 2.0).clamp(0.0, 1.0);
+        packed = (packed << bits) | (t * max_value as f64).round() as u64;
+    }
+    packed
+}
+
+fn __dequantize(packed: u64, bits: u8) -> Quaternion {
+    let max_value = (1u64 << bits) - 1;
+    let mut small = [0.0; 3];
+    for (i, value) in small.iter_mut().rev().enumerate() {
+        let t = ((packed >> (bits as usize * i)) & max_value) as f64 / max_value as f64;
+        *value = (t * 2.0 - 1.0) / std::f64::consts::SQRT_2;
+    }
+    let largest = (packed >> (3 * bits as usize)) as usize;
+    let missing = (1.0 - small.iter().map(|c| c * c).sum::<f64>()).max(0.0).sqrt();
+    let mut components = [0.0; 4];
+    let mut small = small.iter();
+    for (i, component) in components.iter_mut().enumerate() {
+        *component = if i == largest { missing } else { *small.next().unwrap() };
+    }
+    Quaternion::new(components[0], components[1], components[2], components[3]).normalize()
+}
+
+fn __lerp(a: Position, b: Position, t: f64) -> Position {
+    a + (b - a) * t
+}
+
+fn __slerp(a: Quaternion, b: Quaternion, t: f64) -> Quaternion {
+    a.slerp(__same_hemisphere(b, a), t)
+}
+
+/// Angle (radians) of the rotation between two rotations.
+fn __angle_between(a: Quaternion, b: Quaternion) -> f64 {
+    2.0 * a.dot(b).abs().min(1.0).acos()
+}
+
+/// Values of all frames from the keys.
+fn __expand<V: Copy>(frames: &[usize], values: &[V], num_frames: usize, interpolate: impl Fn(V, V, f64) -> V) -> Vec<V> {
+    (0..num_frames)
+        .map(|frame| {
+            let k = frames.partition_point(|&key_frame| key_frame <= frame);
+            if k == 0 {
+                values[0]
+            } else if k == frames.len() {
+                values[k - 1]
+            } else {
+                let t = (frame - frames[k - 1]) as f64 / (frames[k] - frames[k - 1]) as f64;
+                interpolate(values[k - 1], values[k], t)
+            }
+        })
+        .collect()
+}
+
+/// Frame (other than `from` and `to`) with the largest error when interpolating between the keys at `from` and `to`.
+fn __worst_in_segment<V: Copy>(
+    from: (usize, V),
+    to: (usize, V),
+    interpolate: &impl Fn(V, V, f64) -> V,
+    error: &impl Fn(usize, V) -> f64,
+) -> Option<(usize, f64)> {
+    (from.0 + 1..to.0)
+        .map(|frame| {
+            let t = (frame - from.0) as f64 / (to.0 - from.0) as f64;
+            (frame, error(frame, interpolate(from.1, to.1, t)))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Greedy keyframe reduction: start with keys at the first and last frame, then keep adding a key at the frame with
+/// the largest error until `error` (of a decoded value at a frame, relative to the tolerance) is at most 1 everywhere.
+/// The worst frame of every segment between two keys is cached, so a new key only re-scores the two segments it splits.
+/// Returns the keys and the decoded track.
+fn __reduce<S: Copy, V: Copy>(
+    track: &[V],
+    encode: impl Fn(V) -> S,
+    decode: impl Fn(S) -> V,
+    interpolate: impl Fn(V, V, f64) -> V,
+    error: impl Fn(usize, V) -> f64,
+) -> (KeyframeTrack<S>, Vec<V>) {
+    let n = track.len();
+    let mut frames: Vec<usize> = if n > 1 { vec![0, n - 1] } else { vec![0] };
+    let mut keys: Vec<S> = frames.iter().map(|&f| encode(track[f])).collect();
+    let mut values: Vec<V> = keys.iter().map(|&key| decode(key)).collect();
+    // worst frame between keys k and k + 1
+    let mut segments: Vec<Option<(usize, f64)>> = (1..frames.len())
+        .map(|k| __worst_in_segment((frames[k - 1], values[k - 1]), (frames[k], values[k]), &interpolate, &error))
+        .collect();
+    loop {
+        let worst = segments
+            .iter()
+            .enumerate()
+            .filter_map(|(k, worst)| worst.map(|(frame, e)| (k, frame, e)))
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((k, frame, e)) = worst else { break };
+        if e <= 1.0 {
+            break;
+        }
+        let key = encode(track[frame]);
+        let value = decode(key);
+        frames.insert(k + 1, frame);
+        keys.insert(k + 1, key);
+        values.insert(k + 1, value);
+        let left = __worst_in_segment((frames[k], values[k]), (frame, value), &interpolate, &error);
+        let right = __worst_in_segment((frame, value), (frames[k + 2], values[k + 2]), &interpolate, &error);
+        segments.splice(k..=k, [left, right]);
+    }
+    let decoded = __expand(&frames, &values, n, &interpolate);
+    (KeyframeTrack { frames, values: keys }, decoded)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+const POSITION_KEY_BYTES: usize = 12;
+const ROTATION_KEY_BYTES: usize = 16;
+
+/// Bytes of a frame index: the smallest whole number of bytes that can address every frame.
+fn __frame_index_bytes(num_frames: usize) -> usize {
+    let bits = usize::BITS - num_frames.saturating_sub(1).leading_zeros();
+    (bits as usize).div_ceil(8).max(1)
+}
+
+impl RotationTrack {
+    pub fn num_keys(&self) -> usize {
+        match self {
+            RotationTrack::Full(keys) => keys.frames.len(),
+            RotationTrack::Quantized { keys, .. } => keys.frames.len(),
+        }
+    }
+
+    fn size_in_bytes(&self, num_frames: usize) -> usize {
+        let key_bytes = match self {
+            RotationTrack::Full(_) => ROTATION_KEY_BYTES,
+            RotationTrack::Quantized { bits, .. } => (2 + 3 * *bits as usize).div_ceil(8),
+        };
+        self.num_keys() * (key_bytes + __frame_index_bytes(num_frames))
+    }
+
+    /// Rotations of all frames.
+    pub fn decompress(&self, num_frames: usize) -> Vec<Quaternion> {
+        match self {
+            RotationTrack::Full(keys) => __expand(&keys.frames, &keys.values, num_frames, __slerp),
+            RotationTrack::Quantized { bits, keys } => {
+                let values: Vec<Quaternion> = keys.values.iter().map(|&key| __dequantize(key, *bits)).collect();
+                __expand(&keys.frames, &values, num_frames, __slerp)
+            }
+        }
+    }
+}
+
+impl CompressedAnimation {
+    pub fn num_keys(&self) -> usize {
+        self.root_positions.frames.len() + self.rotations.iter().map(|track| track.num_keys()).sum::<usize>()
+    }
+
+    /// Size of the keys (32-bit floats, frame indices with as few bytes as `num_frames` allows).
+    pub fn size_in_bytes(&self) -> usize {
+        self.root_positions.frames.len() * (POSITION_KEY_BYTES + __frame_index_bytes(self.num_frames))
+            + self.rotations.iter().map(|track| track.size_in_bytes(self.num_frames)).sum::<usize>()
+    }
+
+    /// Reconstruct the full-rate animation. `skeleton` provides the rest pose (e.g. the original `BvhData`).
+    pub fn decompress(&self, metadata: &BvhMetadata, skeleton: &BvhData) -> (BvhMetadata, BvhData) {
+        assert_eq!(metadata.joints.len(), self.rotations.len(), "Compressed animation has different number of joints!");
+        let root_positions = __expand(&self.root_positions.frames, &self.root_positions.values, self.num_frames, __lerp);
+        let local_rotations = self.rotations.iter().map(|track| track.decompress(self.num_frames)).collect();
+        let new_metadata = BvhMetadata {
+            num_frames: self.num_frames,
+            frame_time: self.frame_time,
+            ..metadata.clone()
+        };
+        let new_data = __build_bvh_data(&new_metadata, skeleton, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Compress the animation by keyframe reduction of every track (root translation and local rotations).
+    ///
+    /// Joints are reduced parents-first, each against the already compressed parents (compensating their error), so that the global
+    /// rotation of the joint and the global positions of its children and endsite stay within the tolerances of `settings`.
+    pub fn compress(&self, metadata: &BvhMetadata, settings: &CompressionSettings) -> (CompressedAnimation, CompressionReport) {
+        assert!(
+            settings.max_position_error > 0.0 && settings.max_angle_error > 0.0,
+            "Compression tolerances have to be positive!"
+        );
+        if let Some(bits) = settings.quantization_bits {
+            assert!((2..=20).contains(&bits), "Quantization bits have to be between 2 and 20!");
+        }
+        let n = metadata.num_frames;
+        assert!(n > 0, "No frames to compress!");
+        let num_joints = metadata.joints.len();
+
+        //// root translation
+        let original_root = &self.pose_global_positions[0][..n];
+        let (root_positions, root_decoded) = __reduce(
+            original_root,
+            |p| p,
+            |p| p,
+            __lerp,
+            |f, p| (p - original_root[f]).magnitude() / settings.max_position_error,
+        );
+
+        //// rotations, parents-first, with the global pose of the compressed joints
+        let mut positions: Vec<Vec<Position>> = vec![Vec::new(); num_joints];
+        let mut rotations: Vec<Vec<Quaternion>> = vec![Vec::new(); num_joints];
+        let mut tracks: Vec<Option<RotationTrack>> = vec![None; num_joints];
+        positions[0] = root_decoded;
+        for joint in metadata.joints.iter() {
+            let j = joint.index;
+            let parent_rotations: Vec<Quaternion> = match joint.parent_index {
+                -1 => vec![Quaternion::identity(); n],
+                parent => rotations[parent as Index].clone(),
+            };
+            if joint.parent_index != -1 {
+                let parent = joint.parent_index as Index;
+                positions[j] = (0..n)
+                    .map(|f| positions[parent][f] + parent_rotations[f] * self.rest_local_positions[j])
+                    .collect();
+            }
+
+            //// points moved by the joint: children and endsite (offset, original positions)
+            let mut ends: Vec<(Position, Vec<Position>)> = joint
+                .children
+                .iter()
+                .map(|&child| (self.rest_local_positions[child], self.pose_global_positions[child][..n].to_vec()))
+                .collect();
+            if let Some(endsite) = &joint.endsite {
+                let original = (0..n)
+                    .map(|f| self.pose_global_positions[j][f] + self.pose_global_rotations[j][f] * endsite.offset)
+                    .collect();
+                ends.push((endsite.offset, original));
+            }
+
+            let error = |f: usize, decoded: Quaternion| -> f64 {
+                let global = parent_rotations[f] * decoded;
+                let mut error = __angle_between(global, self.pose_global_rotations[j][f]) / settings.max_angle_error;
+                for (offset, original) in ends.iter() {
+                    let position = positions[j][f] + global * offset;
+                    error = error.max((position - original[f]).magnitude() / settings.max_position_error);
+                }
+                error
+            };
+            // local rotations compensating the error of the compressed parents, so that keys restore the original global rotation
+            let original: Vec<Quaternion> =
+                (0..n).map(|f| parent_rotations[f].conjugate() * self.pose_global_rotations[j][f]).collect();
+            let original = &original[..];
+            let (track, decoded) = match settings.quantization_bits {
+                None => {
+                    let (keys, decoded) = __reduce(original, |q| q, |q| q, __slerp, error);
+                    (RotationTrack::Full(keys), decoded)
+                }
+                Some(bits) => {
+                    let (keys, decoded) = __reduce(original, |q| __quantize(q, bits), |key| __dequantize(key, bits), __slerp, error);
+                    (RotationTrack::Quantized { bits, keys }, decoded)
+                }
+            };
+            rotations[j] = (0..n).map(|f| parent_rotations[f] * decoded[f]).collect();
+            tracks[j] = Some(track);
+        }
+
+        let compressed = CompressedAnimation {
+            num_frames: n,
+            frame_time: metadata.frame_time,
+            root_positions,
+            rotations: tracks.into_iter().map(|track| track.unwrap()).collect(),
+        };
+
+        //// report, measured on the decompressed animation
+        let (_, decompressed) = compressed.decompress(metadata, self);
+        let mut max_position_error: f64 = 0.0;
+        let mut max_angle_error: f64 = 0.0;
+        for j in 0..num_joints {
+            for f in 0..n {
+                max_position_error =
+                    max_position_error.max((decompressed.pose_global_positions[j][f] - self.pose_global_positions[j][f]).magnitude());
+                max_angle_error =
+                    max_angle_error.max(__angle_between(decompressed.pose_global_rotations[j][f], self.pose_global_rotations[j][f]));
+            }
+        }
+        let original_bytes = n * (POSITION_KEY_BYTES + num_joints * ROTATION_KEY_BYTES);
+        let compressed_bytes = compressed.size_in_bytes();
+        let report = CompressionReport {
+            original_keys: n * (num_joints + 1),
+            compressed_keys: compressed.num_keys(),
+            original_bytes,
+            compressed_bytes,
+            compression_ratio: original_bytes as f64 / compressed_bytes as f64,
+            max_position_error,
+            max_angle_error,
+        };
+        (compressed, report)
+    }
+}
@@ -0,0 +1,170 @@
+use crate::derivatives::Difference;
+use crate::humanoid::HumanoidBone;
+use crate::types::*;
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Thresholds for foot contact detection (in the units of the skeleton, height along +Y).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactSettings {
+    /// A joint can get into contact when it's at most this high above its rest clearance over the ground (see `BvhData::rest_clearance`).
+    pub height_threshold: f64,
+    /// A joint can get into contact when it's moving at most this fast (units per second).
+    pub velocity_threshold: f64,
+    /// Relative widening of both thresholds for leaving a contact (e.g. 0.5 = a contact ends only at 1.5x the thresholds).
+    /// Prevents contacts from flickering when the foot hovers around the thresholds.
+    pub hysteresis: f64,
+}
+
+impl ContactSettings {
+    /// Reasonable thresholds scaled to the height of the character (5 cm and 0.5 m/s for a 1.7 m tall character).
+    pub fn for_character_height(height: f64) -> ContactSettings {
+        ContactSettings {
+            height_threshold: height * 0.03,
+            velocity_threshold: height * 0.3,
+            hysteresis: 0.5,
+        }
+    }
+}
+
+/// Per-frame contact labels of a set of joints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FootContacts {
+    pub joints: Vec<Index>,
+    /// `[joint (in the order of joints)][frame]`
+    pub contacts: Vec<Vec<bool>>,
+    /// Estimated ground height (Y).
+    pub ground_height: f64,
+}
+
+impl FootContacts {
+    /// Contact labels of a joint (`None` if the joint wasn't part of the detection).
+    pub fn of_joint(&self, index: Index) -> Option<&Vec<bool>> {
+        self.joints.iter().position(|&joint| joint == index).map(|i| &self.contacts[i])
+    }
+
+    /// Serialize as CSV: a header with `frame` and the joint names, then a row of 0/1 labels per frame.
+    pub fn to_csv(&self, metadata: &BvhMetadata) -> String {
+        let mut csv = String::from("frame");
+        for &joint in self.joints.iter() {
+            csv.push(',');
+            csv.push_str(&metadata.joints[joint].name);
+        }
+        csv.push('\n');
+        let num_frames = self.contacts.first().map_or(0, |track| track.len());
+        for frame in 0..num_frames {
+            csv.push_str(&frame.to_string());
+            for track in self.contacts.iter() {
+                csv.push_str(if track[frame] { ",1" } else { ",0" });
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Save as a CSV file (see `to_csv`), e.g. next to the .bvh file.
+    pub fn save_csv(&self, metadata: &BvhMetadata, file_path: &str) -> std::io::Result<()> {
+        std::fs::write(file_path, self.to_csv(metadata))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Value at the given percentile (0.0 - 1.0) of the values.
+fn __percentile(mut values: Vec<f64>, percentile: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    values[((values.len() - 1) as f64 * percentile).round() as usize]
+}
+
+/// Heights below this percentile of a joint are treated as outliers (e.g. a single glitchy frame) when looking for floor levels.
+const FLOOR_PERCENTILE: f64 = 0.05;
+
+impl BvhData {
+    /// Height of a joint above the lowest endsite of the skeleton in the rest pose, i.e. how high above the ground
+    /// the joint is when the character stands (e.g. the ankle above the tips of the toes).
+    ///
+    /// Only meaningful if the rest pose is a standing pose. If it's not (e.g. all bones along one axis),
+    /// make a standing frame the rest pose first with `rebase_rest_pose`.
+    pub fn rest_clearance(&self, metadata: &BvhMetadata, joint: Index) -> f64 {
+        let lowest_endsite = metadata
+            .joints
+            .iter()
+            .filter_map(|j| {
+                let endsite = j.endsite.as_ref()?;
+                Some((self.rest_global_positions[j.index] + endsite.offset).y)
+            })
+            .fold(f64::MAX, f64::min);
+        let lowest = lowest_endsite.min(self.rest_global_positions.iter().map(|p| p.y).fold(f64::MAX, f64::min));
+        self.rest_global_positions[joint].y - lowest
+    }
+
+    /// Estimate the ground height (Y) from the floor levels of the given (foot) joints, each lowered by its rest clearance.
+    pub fn estimate_ground_height(&self, metadata: &BvhMetadata, joints: &[Index]) -> f64 {
+        joints
+            .iter()
+            .map(|&joint| {
+                let heights = self.pose_global_positions[joint][..metadata.num_frames].iter().map(|p| p.y).collect();
+                __percentile(heights, FLOOR_PERCENTILE) - self.rest_clearance(metadata, joint)
+            })
+            .fold(f64::MAX, f64::min)
+    }
+
+    /// Detect contacts of the given (foot, toe) joints from their height and speed.
+    ///
+    /// Heights are measured from the ground (`y - ground_height`) minus the joint's rest clearance (see `rest_clearance`),
+    /// so an ankle is on the floor when it's as high above the ground as in the rest pose.
+    pub fn detect_foot_contacts(&self, metadata: &BvhMetadata, joints: &[Index], settings: &ContactSettings) -> FootContacts {
+        let ground_height = self.estimate_ground_height(metadata, joints);
+        let velocities = self.linear_velocities(metadata, Difference::Central, None);
+        let release = 1.0 + settings.hysteresis;
+
+        let contacts = joints
+            .iter()
+            .map(|&joint| {
+                let positions = &self.pose_global_positions[joint][..metadata.num_frames];
+                let clearance = self.rest_clearance(metadata, joint);
+                let mut in_contact = false;
+                positions
+                    .iter()
+                    .zip(velocities[joint].iter())
+                    .map(|(position, velocity)| {
+                        let height = position.y - ground_height - clearance;
+                        let speed = velocity.magnitude();
+                        in_contact = if in_contact {
+                            height <= settings.height_threshold * release && speed <= settings.velocity_threshold * release
+                        } else {
+                            height <= settings.height_threshold && speed <= settings.velocity_threshold
+                        };
+                        in_contact
+                    })
+                    .collect()
+            })
+            .collect();
+
+        FootContacts {
+            joints: joints.to_vec(),
+            contacts,
+            ground_height,
+        }
+    }
+
+    /// Detect contacts of the feet and toes found by `humanoid_mapping`.
+    pub fn detect_humanoid_foot_contacts(&self, metadata: &BvhMetadata, settings: &ContactSettings) -> FootContacts {
+        let mapping = metadata.humanoid_mapping();
+        let joints: Vec<Index> = [
+            HumanoidBone::LeftFoot,
+            HumanoidBone::LeftToes,
+            HumanoidBone::RightFoot,
+            HumanoidBone::RightToes,
+        ]
+        .iter()
+        .filter_map(|&bone| mapping.get(bone))
+        .collect();
+        assert!(!joints.is_empty(), "No feet found in the skeleton!");
+        self.detect_foot_contacts(metadata, &joints, settings)
+    }
+}
@@ -0,0 +1,109 @@
+use crate::types::*;
+use crate::utils::{__convolve_positions, __convolve_rotations, __gaussian_kernel, __quat_log};
+use cgmath::Zero;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Finite difference scheme used for derivatives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    /// `(x[i+1] - x[i-1]) / 2dt` (one-sided at the first and last frame). Centered in time, less noisy.
+    Central,
+    /// `(x[i+1] - x[i]) / dt` (the last frame repeats the previous value). Only depends on the current and next frame.
+    Forward,
+}
+
+/// Reference frame of angular velocities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationSpace {
+    /// From `pose_global_rotations`, expressed in world space.
+    Global,
+    /// From `pose_local_rotations`, i.e. relative to the parent joint, expressed in the parent's frame.
+    Local,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Differentiate a track, `diff(a, b)` is the change from `a` to `b`.
+fn __differentiate<T: Copy, F: Fn(T, T) -> Position>(track: &[T], dt: f64, method: Difference, diff: F) -> Vec<Position> {
+    let n = track.len();
+    if n < 2 {
+        return vec![Position::zero(); n];
+    }
+    (0..n)
+        .map(|i| match method {
+            Difference::Central if i > 0 && i < n - 1 => diff(track[i - 1], track[i + 1]) / (2.0 * dt),
+            Difference::Central if i == 0 => diff(track[0], track[1]) / dt,
+            Difference::Central => diff(track[n - 2], track[n - 1]) / dt,
+            Difference::Forward => {
+                let i = i.min(n - 2);
+                diff(track[i], track[i + 1]) / dt
+            }
+        })
+        .collect()
+}
+
+/// Angular velocity (axis * radians) of the rotation from `a` to `b` (`b * a^-1`).
+fn __rotation_difference(a: Quaternion, b: Quaternion) -> Position {
+    let delta = b * a.conjugate();
+    let delta = if delta.s < 0.0 { -delta } else { delta };
+    __quat_log(delta) * 2.0
+}
+
+/// Position tracks, optionally smoothed with a Gaussian of `smoothing` sigma (in frames).
+fn __smoothed_positions(data: &BvhData, smoothing: Option<f64>) -> Vec<Vec<Position>> {
+    match smoothing {
+        Some(sigma) if sigma > 0.0 => {
+            let kernel = __gaussian_kernel(sigma);
+            data.pose_global_positions.iter().map(|track| __convolve_positions(track, &kernel)).collect()
+        }
+        _ => data.pose_global_positions.clone(),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Linear velocity (units per second) of every joint, `[joint][frame]`.
+    /// `smoothing` is the sigma (in frames) of a Gaussian applied to the positions before differentiating.
+    pub fn linear_velocities(&self, metadata: &BvhMetadata, method: Difference, smoothing: Option<f64>) -> Vec<Vec<Position>> {
+        __smoothed_positions(self, smoothing)
+            .iter()
+            .map(|track| __differentiate(&track[..metadata.num_frames], metadata.frame_time, method, |a, b| b - a))
+            .collect()
+    }
+
+    /// Linear acceleration (units per second squared) of every joint, `[joint][frame]`, i.e. the derivative of `linear_velocities`.
+    pub fn linear_accelerations(&self, metadata: &BvhMetadata, method: Difference, smoothing: Option<f64>) -> Vec<Vec<Position>> {
+        self.linear_velocities(metadata, method, smoothing)
+            .iter()
+            .map(|track| __differentiate(track, metadata.frame_time, method, |a, b| b - a))
+            .collect()
+    }
+
+    /// Angular velocity (rotation axis scaled by radians per second) of every joint, `[joint][frame]`.
+    /// `smoothing` is the sigma (in frames) of a Gaussian applied to the rotations (in their tangent space) before differentiating.
+    pub fn angular_velocities(
+        &self,
+        metadata: &BvhMetadata,
+        space: RotationSpace,
+        method: Difference,
+        smoothing: Option<f64>,
+    ) -> Vec<Vec<Position>> {
+        let tracks = match space {
+            RotationSpace::Global => &self.pose_global_rotations,
+            RotationSpace::Local => &self.pose_local_rotations,
+        };
+        let kernel = smoothing.filter(|&sigma| sigma > 0.0).map(__gaussian_kernel);
+        tracks
+            .iter()
+            .map(|track| {
+                let track = &track[..metadata.num_frames];
+                match &kernel {
+                    Some(kernel) => __differentiate(&__convolve_rotations(track, kernel), metadata.frame_time, method, __rotation_difference),
+                    None => __differentiate(track, metadata.frame_time, method, __rotation_difference),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::parse::__build_bvh_data;
+use crate::root_motion::RootTrajectory;
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Root displacement between two frames, expressed in the character frame (ground-projected root + heading) of the previous frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RootDelta {
+    pub dx: f64,
+    pub dz: f64,
+    /// Change of heading (rotation around Y) in radians.
+    pub dyaw: f64,
+}
+
+/// Character-relative representation of an animation used by motion models (PFNN, MotionVAE, ...).
+/// All per-frame data is laid out as `[frame][joint]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterFrameEncoding {
+    /// Ground-projected root position of the first frame (world space).
+    pub initial_position: Position,
+    /// Heading of the first frame (world space).
+    pub initial_heading: f64,
+    /// Per-frame root deltas (the first one is always zero).
+    pub root_deltas: Vec<RootDelta>,
+    /// Joint positions relative to the character frame (so the root entry holds the root height).
+    pub joint_positions: Vec<Vec<Position>>,
+    /// Local joint rotations, root rotation relative to the character frame.
+    pub local_rotations: Vec<Vec<Quaternion>>,
+}
+
+impl CharacterFrameEncoding {
+    pub fn num_frames(&self) -> usize {
+        self.root_deltas.len()
+    }
+
+    /// Integrate root deltas back into a world-space ground trajectory.
+    pub fn integrate_trajectory(&self) -> RootTrajectory {
+        let mut positions = Vec::with_capacity(self.num_frames());
+        let mut headings = Vec::with_capacity(self.num_frames());
+        let mut position = self.initial_position;
+        let mut heading = self.initial_heading;
+        for (frame, delta) in self.root_deltas.iter().enumerate() {
+            if frame > 0 {
+                let (sin, cos) = heading.sin_cos();
+                // rotation around Y applied to (dx, 0, dz)
+                position += Position::new(cos * delta.dx + sin * delta.dz, 0.0, -sin * delta.dx + cos * delta.dz);
+                heading += delta.dyaw;
+            }
+            positions.push(position);
+            headings.push(heading);
+        }
+        RootTrajectory { positions, headings }
+    }
+
+    /// Decode back into world-space `BvhData` (rest pose is taken from `data`, which must share the skeleton).
+    pub fn decode(&self, metadata: &BvhMetadata, data: &BvhData) -> BvhData {
+        let trajectory = self.integrate_trajectory();
+        let num_joints = metadata.joints.len();
+        let mut root_positions = Vec::with_capacity(self.num_frames());
+        let mut local_rotations = vec![Vec::with_capacity(self.num_frames()); num_joints];
+        for frame in 0..self.num_frames() {
+            let heading = trajectory.rotation(frame);
+            root_positions.push(trajectory.positions[frame] + heading * self.joint_positions[frame][0]);
+            for (joint_index, track) in local_rotations.iter_mut().enumerate() {
+                let q = self.local_rotations[frame][joint_index];
+                track.push(if joint_index == 0 { heading * q } else { q });
+            }
+        }
+        let metadata = BvhMetadata {
+            num_frames: self.num_frames(),
+            ..metadata.clone()
+        };
+        __build_bvh_data(&metadata, data, root_positions, local_rotations)
+    }
+
+    /// Flatten a frame into a feature vector: `[dx, dz, dyaw, joint positions (x, y, z)..., local rotations (w, x, y, z)...]`.
+    pub fn frame_features(&self, frame: usize) -> Vec<f64> {
+        let delta = self.root_deltas[frame];
+        let mut features = vec![delta.dx, delta.dz, delta.dyaw];
+        for p in self.joint_positions[frame].iter() {
+            features.extend_from_slice(&[p.x, p.y, p.z]);
+        }
+        for q in self.local_rotations[frame].iter() {
+            features.extend_from_slice(&[q.s, q.v.x, q.v.y, q.v.z]);
+        }
+        features
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Encode the animation into the character-relative representation.
+    /// `forward` is the facing direction in the root joint's local frame (see `root_forward_axis`).
+    pub fn encode_character_frame(&self, metadata: &BvhMetadata, forward: Position) -> CharacterFrameEncoding {
+        let trajectory = self.extract_root_trajectory(metadata, forward);
+        let num_frames = metadata.num_frames;
+
+        let mut root_deltas = Vec::with_capacity(num_frames);
+        let mut joint_positions = Vec::with_capacity(num_frames);
+        let mut local_rotations = Vec::with_capacity(num_frames);
+        for frame in 0..num_frames {
+            //// root delta in the previous frame's character frame
+            root_deltas.push(if frame == 0 {
+                RootDelta { dx: 0.0, dz: 0.0, dyaw: 0.0 }
+            } else {
+                let d = trajectory.rotation(frame - 1).conjugate()
+                    * (trajectory.positions[frame] - trajectory.positions[frame - 1]);
+                RootDelta {
+                    dx: d.x,
+                    dz: d.z,
+                    dyaw: trajectory.headings[frame] - trajectory.headings[frame - 1],
+                }
+            });
+
+            //// joints relative to the current character frame
+            let heading_inv = trajectory.rotation(frame).conjugate();
+            joint_positions.push(
+                self.pose_global_positions
+                    .iter()
+                    .map(|track| heading_inv * (track[frame] - trajectory.positions[frame]))
+                    .collect(),
+            );
+            local_rotations.push(
+                self.pose_local_rotations
+                    .iter()
+                    .enumerate()
+                    .map(|(joint_index, track)| if joint_index == 0 { heading_inv * track[frame] } else { track[frame] })
+                    .collect(),
+            );
+        }
+
+        CharacterFrameEncoding {
+            initial_position: trajectory.positions.first().copied().unwrap_or(Position::identity()),
+            initial_heading: trajectory.headings.first().copied().unwrap_or(0.0),
+            root_deltas,
+            joint_positions,
+            local_rotations,
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use crate::utils::{__gaussian_kernel, __quat_exp, __quat_log, __same_hemisphere};
+use cgmath::{InnerSpace, Zero};
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Smoothing filters for position and rotation tracks. Rotations are filtered in the tangent space (see `TrackValue`),
+/// so filtering is independent of the quaternion signs and safe for large rotations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Gaussian blur, `sigma` in frames.
+    Gaussian { sigma: f64 },
+    /// Local polynomial least-squares fit of `order` over `window` frames (odd). Keeps peaks better than a Gaussian.
+    SavitzkyGolay { window: usize, order: usize },
+    /// 2nd order Butterworth low-pass applied forward and backward (zero phase), `cutoff` in Hz.
+    Butterworth { cutoff: f64 },
+    /// One-euro filter (adaptive low-pass: smooth when slow, responsive when fast). Cutoffs in Hz.
+    /// Causal, so it matches what a real-time (e.g. live capture) pipeline would produce.
+    OneEuro { min_cutoff: f64, beta: f64, derivative_cutoff: f64 },
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A value in a track which can be filtered.
+/// Every filter here is expressed as an affine combination of track values (weights summing to 1),
+/// which for rotations is evaluated in the tangent space of a reference rotation: `r * exp(sum(w * log(r^-1 * q)))`.
+trait TrackValue: Copy {
+    fn affine(reference: Self, terms: &[(Self, f64)]) -> Self;
+    /// Change from `a` to `b` as a vector (for rotations: rotation axis * radians).
+    fn difference(a: Self, b: Self) -> Position;
+}
+
+impl TrackValue for Position {
+    fn affine(_reference: Self, terms: &[(Self, f64)]) -> Self {
+        terms.iter().map(|&(p, w)| p * w).sum()
+    }
+
+    fn difference(a: Self, b: Self) -> Position {
+        b - a
+    }
+}
+
+impl TrackValue for Quaternion {
+    fn affine(reference: Self, terms: &[(Self, f64)]) -> Self {
+        let reference_inv = reference.conjugate();
+        let tangent: Position = terms
+            .iter()
+            .map(|&(q, w)| __quat_log(__same_hemisphere(reference_inv * q, Quaternion::identity())) * w)
+            .sum();
+        (reference * __quat_exp(tangent)).normalize()
+    }
+
+    fn difference(a: Self, b: Self) -> Position {
+        __quat_log(__same_hemisphere(a.conjugate() * b, Quaternion::identity())) * 2.0
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Savitzky-Golay weights for evaluating the fitted polynomial at offset 0, given the sample offsets of the window.
+fn __savitzky_golay_weights(offsets: &[f64], order: usize) -> Vec<f64> {
+    //// normal equations (A^T A) c = e0, weights = A c
+    let n = order + 1;
+    let mut m = vec![vec![0.0; n + 1]; n];
+    for (row, m_row) in m.iter_mut().enumerate() {
+        for (col, value) in m_row.iter_mut().take(n).enumerate() {
+            *value = offsets.iter().map(|t| t.powi((row + col) as i32)).sum();
+        }
+        m_row[n] = if row == 0 { 1.0 } else { 0.0 };
+    }
+    //// gaussian elimination with partial pivoting
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs())).unwrap();
+        m.swap(col, pivot);
+        let pivot_row = m[col].clone();
+        if pivot_row[col] == 0.0 {
+            continue;
+        }
+        for (row, m_row) in m.iter_mut().enumerate() {
+            if row != col {
+                let factor = m_row[col] / pivot_row[col];
+                for (value, p) in m_row.iter_mut().zip(pivot_row.iter()).skip(col) {
+                    *value -= factor * p;
+                }
+            }
+        }
+    }
+    let c: Vec<f64> = (0..n).map(|i| m[i][n] / m[i][i]).collect();
+    offsets
+        .iter()
+        .map(|t| c.iter().enumerate().map(|(j, c_j)| c_j * t.powi(j as i32)).sum())
+        .collect()
+}
+
+/// FIR filtering: `weights(i)` gives (frame, weight) pairs for frame `i`, combined around the value of frame `i`.
+fn __apply_fir<T: TrackValue>(track: &[T], weights: impl Fn(usize) -> Vec<(usize, f64)>) -> Vec<T> {
+    (0..track.len())
+        .map(|i| {
+            let terms: Vec<(T, f64)> = weights(i).into_iter().map(|(j, w)| (track[j], w)).collect();
+            T::affine(track[i], &terms)
+        })
+        .collect()
+}
+
+/// One pass of the 2nd order Butterworth low-pass. Starts in the steady state of the first value.
+fn __butterworth_pass<T: TrackValue>(track: &[T], cutoff: f64, frame_time: f64) -> Vec<T> {
+    let k = (std::f64::consts::PI * cutoff * frame_time).tan();
+    let sqrt2 = std::f64::consts::SQRT_2;
+    let norm = 1.0 / (1.0 + sqrt2 * k + k * k);
+    let b0 = k * k * norm;
+    let (b1, b2) = (2.0 * b0, b0);
+    let a1 = 2.0 * (k * k - 1.0) * norm;
+    let a2 = (1.0 - sqrt2 * k + k * k) * norm;
+
+    let mut output: Vec<T> = Vec::with_capacity(track.len());
+    for i in 0..track.len() {
+        let x = |d: usize| track[i.saturating_sub(d)];
+        let y = |d: usize| if i >= d { output[i - d] } else { track[0] };
+        let terms = [(x(0), b0), (x(1), b1), (x(2), b2), (y(1), -a1), (y(2), -a2)];
+        output.push(T::affine(y(1), &terms));
+    }
+    output
+}
+
+fn __one_euro<T: TrackValue>(track: &[T], frame_time: f64, min_cutoff: f64, beta: f64, derivative_cutoff: f64) -> Vec<T> {
+    let alpha = |cutoff: f64| {
+        let tau = 1.0 / (2.0 * std::f64::consts::PI * cutoff);
+        1.0 / (1.0 + tau / frame_time)
+    };
+    let mut output: Vec<T> = Vec::with_capacity(track.len());
+    let mut derivative = Position::zero();
+    for (i, &x) in track.iter().enumerate() {
+        if i == 0 {
+            output.push(x);
+            continue;
+        }
+        let previous = output[i - 1];
+        let a_d = alpha(derivative_cutoff);
+        derivative = derivative * (1.0 - a_d) + T::difference(previous, x) / frame_time * a_d;
+        let a = alpha(min_cutoff + beta * derivative.magnitude());
+        output.push(T::affine(previous, &[(previous, 1.0 - a), (x, a)]));
+    }
+    output
+}
+
+impl Filter {
+    fn __apply<T: TrackValue>(&self, track: &[T], frame_time: f64) -> Vec<T> {
+        let n = track.len();
+        match *self {
+            Filter::Gaussian { sigma } => {
+                if sigma <= 0.0 {
+                    return track.to_vec();
+                }
+                let kernel = __gaussian_kernel(sigma);
+                let radius = kernel.len() / 2;
+                __apply_fir(track, |i| {
+                    //// truncated and renormalized at the borders
+                    let frames = i.saturating_sub(radius)..(i + radius + 1).min(n);
+                    let sum: f64 = frames.clone().map(|j| kernel[j + radius - i]).sum();
+                    frames.map(|j| (j, kernel[j + radius - i] / sum)).collect()
+                })
+            }
+            Filter::SavitzkyGolay { window, order } => {
+                assert!(window % 2 == 1 && window > order, "Savitzky-Golay window has to be odd and larger than the order!");
+                if n < window {
+                    return track.to_vec();
+                }
+                let radius = window / 2;
+                __apply_fir(track, |i| {
+                    //// at the borders the window is shifted to stay inside the track
+                    let start = i.saturating_sub(radius).min(n - window);
+                    let offsets: Vec<f64> = (start..start + window).map(|j| j as f64 - i as f64).collect();
+                    (start..start + window).zip(__savitzky_golay_weights(&offsets, order)).collect()
+                })
+            }
+            Filter::Butterworth { cutoff } => {
+                assert!(
+                    cutoff > 0.0 && cutoff < 0.5 / frame_time,
+                    "Butterworth cutoff has to be between 0 and half of the sampling rate!"
+                );
+                let forward = __butterworth_pass(track, cutoff, frame_time);
+                let reversed: Vec<T> = forward.into_iter().rev().collect();
+                __butterworth_pass(&reversed, cutoff, frame_time).into_iter().rev().collect()
+            }
+            Filter::OneEuro { min_cutoff, beta, derivative_cutoff } => __one_euro(track, frame_time, min_cutoff, beta, derivative_cutoff),
+        }
+    }
+
+    /// Filter a position track. `frame_time` is in seconds.
+    pub fn apply_to_positions(&self, track: &[Position], frame_time: f64) -> Vec<Position> {
+        self.__apply(track, frame_time)
+    }
+
+    /// Filter a rotation track (in the tangent space). `frame_time` is in seconds.
+    pub fn apply_to_rotations(&self, track: &[Quaternion], frame_time: f64) -> Vec<Quaternion> {
+        self.__apply(track, frame_time)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Flip quaternions so consecutive frames lie in the same hemisphere.
+fn __make_continuous(track: &mut [Quaternion]) {
+    for i in 1..track.len() {
+        track[i] = __same_hemisphere(track[i], track[i - 1]);
+    }
+}
+
+impl BvhData {
+    /// Flip signs of quaternions in `pose_local_rotations` and `pose_global_rotations`, so that no track jumps between `q` and `-q`
+    /// (both are the same rotation) from one frame to the next.
+    pub fn enforce_quaternion_continuity(&mut self) {
+        for track in self.pose_local_rotations.iter_mut().chain(self.pose_global_rotations.iter_mut()) {
+            __make_continuous(track);
+        }
+    }
+
+    /// Smooth local rotations of all joints. Global pose is recalculated.
+    pub fn filter_rotations(&self, metadata: &BvhMetadata, filter: Filter) -> BvhData {
+        let local_rotations = self
+            .pose_local_rotations
+            .iter()
+            .map(|track| {
+                let mut track = filter.apply_to_rotations(&track[..metadata.num_frames], metadata.frame_time);
+                __make_continuous(&mut track);
+                track
+            })
+            .collect();
+        __build_bvh_data(metadata, self, self.pose_global_positions[0].clone(), local_rotations)
+    }
+
+    /// Smooth the root translation. Global pose is recalculated.
+    pub fn filter_root_positions(&self, metadata: &BvhMetadata, filter: Filter) -> BvhData {
+        let root_positions = filter.apply_to_positions(&self.pose_global_positions[0][..metadata.num_frames], metadata.frame_time);
+        __build_bvh_data(metadata, self, root_positions, self.pose_local_rotations.clone())
+    }
+}
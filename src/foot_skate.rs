@@ -0,0 +1,102 @@
+use crate::blend::__smoothstep;
+use crate::contact::FootContacts;
+use crate::humanoid::HumanoidBone;
+use crate::ik::{solve_two_bone, TwoBoneChain};
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Contact segments (first frame, last frame) of a contact track.
+fn __contact_segments(contacts: &[bool]) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    for (frame, &contact) in contacts.iter().enumerate() {
+        match (contact, start) {
+            (true, None) => start = Some(frame),
+            (false, Some(s)) => {
+                segments.push((s, frame - 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        segments.push((s, contacts.len() - 1));
+    }
+    segments
+}
+
+impl BvhData {
+    /// Remove foot sliding: during every contact the `end` joint of each leg is pinned to its average position over the contact,
+    /// and the leg is solved with two-bone IK (knee keeps pointing the same way, foot keeps its global rotation).
+    /// The correction blends in and out over `blend_frames` frames around the contacts. Global pose is recalculated.
+    ///
+    /// `contacts` must contain labels for the `end` joint of every leg (see `detect_foot_contacts`).
+    pub fn fix_foot_skate(&self, metadata: &BvhMetadata, contacts: &FootContacts, legs: &[TwoBoneChain], blend_frames: usize) -> BvhData {
+        let num_frames = metadata.num_frames;
+
+        //// (target position, weight) of every leg in every frame
+        let mut targets: Vec<Vec<(Position, f64)>> = Vec::with_capacity(legs.len());
+        for leg in legs.iter() {
+            let labels = contacts
+                .of_joint(leg.end)
+                .unwrap_or_else(|| panic!("No contact labels for joint {}!", metadata.joints[leg.end].name));
+            let positions = &self.pose_global_positions[leg.end];
+            let mut leg_targets: Vec<(Position, f64)> = positions.iter().take(num_frames).map(|&p| (p, 0.0)).collect();
+            for (start, end) in __contact_segments(labels) {
+                let pin: Position = positions[start..=end].iter().sum::<Position>() / (end - start + 1) as f64;
+                let from = start.saturating_sub(blend_frames);
+                let to = (end + blend_frames).min(num_frames - 1);
+                for (frame, target) in leg_targets.iter_mut().enumerate().take(to + 1).skip(from) {
+                    let distance = if frame < start {
+                        start - frame
+                    } else {
+                        frame.saturating_sub(end)
+                    };
+                    let weight = 1.0 - __smoothstep(distance as f64 / (blend_frames + 1) as f64);
+                    // overlapping blends: the closer contact wins
+                    if weight > target.1 {
+                        *target = (positions[frame] + (pin - positions[frame]) * weight, weight);
+                    }
+                }
+            }
+            targets.push(leg_targets);
+        }
+
+        //// solve
+        let mut local_rotations = self.pose_local_rotations.clone();
+        for frame in 0..num_frames {
+            if targets.iter().all(|leg_targets| leg_targets[frame].1 == 0.0) {
+                continue;
+            }
+            let mut pose = self.pose_at_frame(frame);
+            for (leg, leg_targets) in legs.iter().zip(targets.iter()) {
+                let (target, weight) = leg_targets[frame];
+                if weight > 0.0 {
+                    let pole = Some(pose.global_positions[leg.middle]);
+                    let foot_rotation = Some(pose.global_rotations[leg.end]);
+                    pose = solve_two_bone(metadata, self, &pose, *leg, target, foot_rotation, pole);
+                }
+            }
+            for (track, &q) in local_rotations.iter_mut().zip(pose.local_rotations.iter()) {
+                track[frame] = q;
+            }
+        }
+        __build_bvh_data(metadata, self, self.pose_global_positions[0].clone(), local_rotations)
+    }
+
+    /// `fix_foot_skate` for the legs (UpperLeg, LowerLeg, Foot) found by `humanoid_mapping`.
+    pub fn fix_humanoid_foot_skate(&self, metadata: &BvhMetadata, contacts: &FootContacts, blend_frames: usize) -> BvhData {
+        let mapping = metadata.humanoid_mapping();
+        let leg = |bones: [HumanoidBone; 3]| {
+            let [root, middle, end] = bones.map(|bone| mapping.get(bone).unwrap_or_else(|| panic!("Humanoid bone {:?} not found!", bone)));
+            TwoBoneChain { root, middle, end }
+        };
+        let legs = [
+            leg([HumanoidBone::LeftUpperLeg, HumanoidBone::LeftLowerLeg, HumanoidBone::LeftFoot]),
+            leg([HumanoidBone::RightUpperLeg, HumanoidBone::RightLowerLeg, HumanoidBone::RightFoot]),
+        ];
+        self.fix_foot_skate(metadata, contacts, &legs, blend_frames)
+    }
+}
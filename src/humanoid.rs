@@ -0,0 +1,371 @@
+use crate::mirror::NamePairing;
+use crate::retarget::JointMap;
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Canonical humanoid bones (a subset of the Unity humanoid avatar, without fingers).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HumanoidBone {
+    Hips,
+    Spine,
+    Chest,
+    UpperChest,
+    Neck,
+    Head,
+    LeftShoulder,
+    LeftUpperArm,
+    LeftLowerArm,
+    LeftHand,
+    RightShoulder,
+    RightUpperArm,
+    RightLowerArm,
+    RightHand,
+    LeftUpperLeg,
+    LeftLowerLeg,
+    LeftFoot,
+    LeftToes,
+    RightUpperLeg,
+    RightLowerLeg,
+    RightFoot,
+    RightToes,
+}
+
+impl HumanoidBone {
+    pub const ALL: [HumanoidBone; 22] = [
+        HumanoidBone::Hips,
+        HumanoidBone::Spine,
+        HumanoidBone::Chest,
+        HumanoidBone::UpperChest,
+        HumanoidBone::Neck,
+        HumanoidBone::Head,
+        HumanoidBone::LeftShoulder,
+        HumanoidBone::LeftUpperArm,
+        HumanoidBone::LeftLowerArm,
+        HumanoidBone::LeftHand,
+        HumanoidBone::RightShoulder,
+        HumanoidBone::RightUpperArm,
+        HumanoidBone::RightLowerArm,
+        HumanoidBone::RightHand,
+        HumanoidBone::LeftUpperLeg,
+        HumanoidBone::LeftLowerLeg,
+        HumanoidBone::LeftFoot,
+        HumanoidBone::LeftToes,
+        HumanoidBone::RightUpperLeg,
+        HumanoidBone::RightLowerLeg,
+        HumanoidBone::RightFoot,
+        HumanoidBone::RightToes,
+    ];
+
+    /// Parent bone in the canonical hierarchy (`None` for hips).
+    pub fn parent(self) -> Option<HumanoidBone> {
+        use HumanoidBone::*;
+        Some(match self {
+            Hips => return None,
+            Spine => Hips,
+            Chest => Spine,
+            UpperChest => Chest,
+            Neck => UpperChest,
+            Head => Neck,
+            LeftShoulder | RightShoulder => UpperChest,
+            LeftUpperArm => LeftShoulder,
+            LeftLowerArm => LeftUpperArm,
+            LeftHand => LeftLowerArm,
+            RightUpperArm => RightShoulder,
+            RightLowerArm => RightUpperArm,
+            RightHand => RightLowerArm,
+            LeftUpperLeg | RightUpperLeg => Hips,
+            LeftLowerLeg => LeftUpperLeg,
+            LeftFoot => LeftLowerLeg,
+            LeftToes => LeftFoot,
+            RightLowerLeg => RightUpperLeg,
+            RightFoot => RightLowerLeg,
+            RightToes => RightFoot,
+        })
+    }
+
+    /// The same bone on the other side of the body (center bones map to themselves).
+    pub fn mirrored(self) -> HumanoidBone {
+        use HumanoidBone::*;
+        match self {
+            LeftShoulder => RightShoulder,
+            LeftUpperArm => RightUpperArm,
+            LeftLowerArm => RightLowerArm,
+            LeftHand => RightHand,
+            RightShoulder => LeftShoulder,
+            RightUpperArm => LeftUpperArm,
+            RightLowerArm => LeftLowerArm,
+            RightHand => LeftHand,
+            LeftUpperLeg => RightUpperLeg,
+            LeftLowerLeg => RightLowerLeg,
+            LeftFoot => RightFoot,
+            LeftToes => RightToes,
+            RightUpperLeg => LeftUpperLeg,
+            RightLowerLeg => LeftLowerLeg,
+            RightFoot => LeftFoot,
+            RightToes => LeftToes,
+            center => center,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Built-in joint naming conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HumanoidPreset {
+    /// Naming of the bundled `test_anim_sword_attack.bvh` (Hips, Spine..Spine3, LeftArm, LeftUpLeg, ...).
+    SwordAttack,
+    /// Mixamo (`mixamorig:` namespace is ignored).
+    Mixamo,
+    /// CMU mocap database BVH conversions (LowerBack, LHipJoint, ...).
+    Cmu,
+    /// Truebones (LeftCollar, LeftShoulder, LeftElbow, LeftWrist, LeftHip, LeftKnee, LeftAnkle, ...).
+    Truebones,
+    /// Rokoko Studio (Spine1..Spine4, LeftThigh, LeftShin, ...).
+    Rokoko,
+    /// SMPL body model (pelvis, left_hip or L_Hip, ...).
+    Smpl,
+}
+
+type PresetTable = &'static [(HumanoidBone, &'static [&'static str])];
+
+/// Center and left side bones only, right side names are derived by swapping left/right (see `NamePairing`).
+const SWORD_ATTACK: PresetTable = &[
+    (HumanoidBone::Hips, &["Hips"]),
+    (HumanoidBone::Spine, &["Spine"]),
+    (HumanoidBone::Chest, &["Spine2"]),
+    (HumanoidBone::UpperChest, &["Spine3"]),
+    (HumanoidBone::Neck, &["Neck"]),
+    (HumanoidBone::Head, &["Head"]),
+    (HumanoidBone::LeftShoulder, &["LeftShoulder"]),
+    (HumanoidBone::LeftUpperArm, &["LeftArm"]),
+    (HumanoidBone::LeftLowerArm, &["LeftForeArm"]),
+    (HumanoidBone::LeftHand, &["LeftHand"]),
+    (HumanoidBone::LeftUpperLeg, &["LeftUpLeg"]),
+    (HumanoidBone::LeftLowerLeg, &["LeftLeg"]),
+    (HumanoidBone::LeftFoot, &["LeftFoot"]),
+    (HumanoidBone::LeftToes, &["LeftToeBase"]),
+];
+
+const MIXAMO: PresetTable = &[
+    (HumanoidBone::Hips, &["Hips"]),
+    (HumanoidBone::Spine, &["Spine"]),
+    (HumanoidBone::Chest, &["Spine1"]),
+    (HumanoidBone::UpperChest, &["Spine2"]),
+    (HumanoidBone::Neck, &["Neck"]),
+    (HumanoidBone::Head, &["Head"]),
+    (HumanoidBone::LeftShoulder, &["LeftShoulder"]),
+    (HumanoidBone::LeftUpperArm, &["LeftArm"]),
+    (HumanoidBone::LeftLowerArm, &["LeftForeArm"]),
+    (HumanoidBone::LeftHand, &["LeftHand"]),
+    (HumanoidBone::LeftUpperLeg, &["LeftUpLeg"]),
+    (HumanoidBone::LeftLowerLeg, &["LeftLeg"]),
+    (HumanoidBone::LeftFoot, &["LeftFoot"]),
+    (HumanoidBone::LeftToes, &["LeftToeBase"]),
+];
+
+const CMU: PresetTable = &[
+    (HumanoidBone::Hips, &["Hips"]),
+    (HumanoidBone::Spine, &["LowerBack"]),
+    (HumanoidBone::Chest, &["Spine"]),
+    (HumanoidBone::UpperChest, &["Spine1"]),
+    (HumanoidBone::Neck, &["Neck"]),
+    (HumanoidBone::Head, &["Head"]),
+    (HumanoidBone::LeftShoulder, &["LeftShoulder"]),
+    (HumanoidBone::LeftUpperArm, &["LeftArm"]),
+    (HumanoidBone::LeftLowerArm, &["LeftForeArm"]),
+    (HumanoidBone::LeftHand, &["LeftHand"]),
+    (HumanoidBone::LeftUpperLeg, &["LeftUpLeg"]),
+    (HumanoidBone::LeftLowerLeg, &["LeftLeg"]),
+    (HumanoidBone::LeftFoot, &["LeftFoot"]),
+    (HumanoidBone::LeftToes, &["LeftToeBase"]),
+];
+
+const TRUEBONES: PresetTable = &[
+    (HumanoidBone::Hips, &["Hips"]),
+    (HumanoidBone::Spine, &["Spine"]),
+    (HumanoidBone::Chest, &["Spine1", "Chest"]),
+    (HumanoidBone::UpperChest, &["Spine2", "Chest2"]),
+    (HumanoidBone::Neck, &["Neck"]),
+    (HumanoidBone::Head, &["Head"]),
+    (HumanoidBone::LeftShoulder, &["LeftCollar"]),
+    (HumanoidBone::LeftUpperArm, &["LeftShoulder"]),
+    (HumanoidBone::LeftLowerArm, &["LeftElbow"]),
+    (HumanoidBone::LeftHand, &["LeftWrist"]),
+    (HumanoidBone::LeftUpperLeg, &["LeftHip"]),
+    (HumanoidBone::LeftLowerLeg, &["LeftKnee"]),
+    (HumanoidBone::LeftFoot, &["LeftAnkle"]),
+    (HumanoidBone::LeftToes, &["LeftToe"]),
+];
+
+const ROKOKO: PresetTable = &[
+    (HumanoidBone::Hips, &["Hips"]),
+    (HumanoidBone::Spine, &["Spine1"]),
+    (HumanoidBone::Chest, &["Spine2"]),
+    (HumanoidBone::UpperChest, &["Spine4", "Spine3"]),
+    (HumanoidBone::Neck, &["Neck"]),
+    (HumanoidBone::Head, &["Head"]),
+    (HumanoidBone::LeftShoulder, &["LeftShoulder"]),
+    (HumanoidBone::LeftUpperArm, &["LeftArm"]),
+    (HumanoidBone::LeftLowerArm, &["LeftForeArm"]),
+    (HumanoidBone::LeftHand, &["LeftHand"]),
+    (HumanoidBone::LeftUpperLeg, &["LeftThigh"]),
+    (HumanoidBone::LeftLowerLeg, &["LeftShin"]),
+    (HumanoidBone::LeftFoot, &["LeftFoot"]),
+    (HumanoidBone::LeftToes, &["LeftToe"]),
+];
+
+const SMPL: PresetTable = &[
+    (HumanoidBone::Hips, &["pelvis"]),
+    (HumanoidBone::Spine, &["spine1"]),
+    (HumanoidBone::Chest, &["spine2"]),
+    (HumanoidBone::UpperChest, &["spine3"]),
+    (HumanoidBone::Neck, &["neck"]),
+    (HumanoidBone::Head, &["head"]),
+    (HumanoidBone::LeftShoulder, &["left_collar", "l_collar"]),
+    (HumanoidBone::LeftUpperArm, &["left_shoulder", "l_shoulder"]),
+    (HumanoidBone::LeftLowerArm, &["left_elbow", "l_elbow"]),
+    (HumanoidBone::LeftHand, &["left_wrist", "l_wrist"]),
+    (HumanoidBone::LeftUpperLeg, &["left_hip", "l_hip"]),
+    (HumanoidBone::LeftLowerLeg, &["left_knee", "l_knee"]),
+    (HumanoidBone::LeftFoot, &["left_ankle", "l_ankle"]),
+    (HumanoidBone::LeftToes, &["left_foot", "l_foot"]),
+];
+
+impl HumanoidPreset {
+    /// All presets in the order used for breaking ties during auto-detection.
+    pub const ALL: [HumanoidPreset; 6] = [
+        HumanoidPreset::SwordAttack,
+        HumanoidPreset::Mixamo,
+        HumanoidPreset::Cmu,
+        HumanoidPreset::Truebones,
+        HumanoidPreset::Rokoko,
+        HumanoidPreset::Smpl,
+    ];
+
+    fn table(self) -> PresetTable {
+        match self {
+            HumanoidPreset::SwordAttack => SWORD_ATTACK,
+            HumanoidPreset::Mixamo => MIXAMO,
+            HumanoidPreset::Cmu => CMU,
+            HumanoidPreset::Truebones => TRUEBONES,
+            HumanoidPreset::Rokoko => ROKOKO,
+            HumanoidPreset::Smpl => SMPL,
+        }
+    }
+
+    /// Candidate joint names of a bone in this preset.
+    pub fn joint_names(self, bone: HumanoidBone) -> Vec<String> {
+        let pairing = NamePairing::default();
+        for &(preset_bone, names) in self.table() {
+            if preset_bone == bone {
+                return names.iter().map(|name| name.to_string()).collect();
+            }
+            if preset_bone.mirrored() == bone && preset_bone != bone {
+                return names.iter().filter_map(|name| pairing.mirror_name(name)).collect();
+            }
+        }
+        Vec::new()
+    }
+}
+
+/// Joint name normalized for matching: namespace (e.g. `mixamorig:`) stripped and lowercased.
+fn __normalize_name(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_lowercase()
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Mapping of canonical humanoid bones to the joints of a skeleton.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HumanoidMapping {
+    pub preset: HumanoidPreset,
+    /// Found bones (in `HumanoidBone::ALL` order).
+    pub bones: Vec<(HumanoidBone, Index)>,
+    /// Canonical bones which weren't found in the skeleton.
+    pub missing_bones: Vec<HumanoidBone>,
+    /// Joints of the skeleton which aren't mapped to any canonical bone (e.g. fingers, twist joints).
+    pub unmapped_joints: Vec<Index>,
+}
+
+impl HumanoidMapping {
+    /// Joint index of a canonical bone.
+    pub fn get(&self, bone: HumanoidBone) -> Option<Index> {
+        self.bones.iter().find(|(b, _)| *b == bone).map(|(_, index)| *index)
+    }
+
+    /// Canonical bone a joint is mapped to.
+    pub fn bone_of(&self, index: Index) -> Option<HumanoidBone> {
+        self.bones.iter().find(|(_, i)| *i == index).map(|(bone, _)| *bone)
+    }
+}
+
+impl BvhMetadata {
+    /// Map joints to canonical humanoid bones using the given naming preset.
+    pub fn humanoid_mapping_with_preset(&self, preset: HumanoidPreset) -> HumanoidMapping {
+        let mut bones = Vec::new();
+        let mut missing_bones = Vec::new();
+        for bone in HumanoidBone::ALL {
+            let candidates: Vec<String> = preset.joint_names(bone).iter().map(|n| __normalize_name(n)).collect();
+            let found = self
+                .joints
+                .iter()
+                .find(|joint| candidates.contains(&__normalize_name(&joint.name)));
+            match found {
+                Some(joint) => bones.push((bone, joint.index)),
+                None => missing_bones.push(bone),
+            }
+        }
+        let unmapped_joints = self
+            .joints
+            .iter()
+            .map(|joint| joint.index)
+            .filter(|index| !bones.iter().any(|(_, i)| i == index))
+            .collect();
+        HumanoidMapping {
+            preset,
+            bones,
+            missing_bones,
+            unmapped_joints,
+        }
+    }
+
+    /// Map joints to canonical humanoid bones, auto-detecting the preset that maps the most bones.
+    pub fn humanoid_mapping(&self) -> HumanoidMapping {
+        let mut best: Option<HumanoidMapping> = None;
+        for preset in HumanoidPreset::ALL {
+            let mapping = self.humanoid_mapping_with_preset(preset);
+            let better = match &best {
+                Some(b) => mapping.bones.len() > b.bones.len(),
+                None => true,
+            };
+            if better {
+                best = Some(mapping);
+            }
+        }
+        best.unwrap()
+    }
+}
+
+impl JointMap {
+    /// Map joints of two humanoid skeletons through the canonical humanoid bones (presets are auto-detected for both).
+    pub fn between_humanoids(source: &BvhMetadata, target: &BvhMetadata) -> JointMap {
+        let source_mapping = source.humanoid_mapping();
+        let target_mapping = target.humanoid_mapping();
+        JointMap {
+            pairs: source_mapping
+                .bones
+                .iter()
+                .filter_map(|&(bone, source_index)| {
+                    target_mapping.get(bone).map(|target_index| {
+                        (
+                            source.joints[source_index].name.clone(),
+                            target.joints[target_index].name.clone(),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+}
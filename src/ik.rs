@@ -0,0 +1,299 @@
+use crate::types::*;
+use crate::utils::{__quat_exp, __quat_log, __rotation_between, __same_hemisphere};
+use cgmath::{InnerSpace, Rad, Rotation3};
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Three joints of a limb: `root` (hip/shoulder), `middle` (knee/elbow) and `end` (ankle/wrist).
+/// `middle` has to be a descendant of `root` and `end` a descendant of `middle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwoBoneChain {
+    pub root: Index,
+    pub middle: Index,
+    pub end: Index,
+}
+
+/// Limits of a joint's local rotation, as ranges of the components of its rotation vector (rotation axis * radians).
+/// E.g. a knee bending only around its X axis: `min = (0, 0, 0)`, `max = (2.5, 0, 0)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AngleLimits {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl AngleLimits {
+    /// Clamp a local rotation into the limits.
+    pub fn clamp(&self, q: Quaternion) -> Quaternion {
+        let v = __quat_log(__same_hemisphere(q, Quaternion::identity())) * 2.0;
+        let v = Position::new(
+            v.x.clamp(self.min.x, self.max.x),
+            v.y.clamp(self.min.y, self.max.y),
+            v.z.clamp(self.min.z, self.max.z),
+        );
+        __quat_exp(v / 2.0)
+    }
+}
+
+/// Set the global rotation of a joint in the pose (by changing its local rotation, clamped to `limits`) and update the pose with forward kinematics.
+pub(crate) fn __set_global_rotation(
+    metadata: &BvhMetadata,
+    data: &BvhData,
+    pose: &mut Pose,
+    index: Index,
+    rotation: Quaternion,
+    limits: Option<&AngleLimits>,
+) {
+    let parent = metadata.joints[index].parent_index;
+    let local = if parent == -1 {
+        rotation
+    } else {
+        pose.global_rotations[parent as Index].conjugate() * rotation
+    };
+    pose.local_rotations[index] = limits.map_or(local, |limits| limits.clamp(local));
+    *pose = Pose::new(metadata, data, pose.root_position, std::mem::take(&mut pose.local_rotations));
+}
+
+/// Analytic two-bone IK: rotate `chain.root` and `chain.middle` so that `chain.end` reaches `target`
+/// (or gets as close as possible when the target is out of reach).
+///
+/// The limb bends in the plane containing `pole` (e.g. a point in front of the knee); without a pole the current bend plane is kept.
+/// If `target_rotation` is given, it becomes the global rotation of `chain.end`, otherwise `chain.end` keeps its local rotation.
+pub fn solve_two_bone(
+    metadata: &BvhMetadata,
+    data: &BvhData,
+    pose: &Pose,
+    chain: TwoBoneChain,
+    target: Position,
+    target_rotation: Option<Quaternion>,
+    pole: Option<Position>,
+) -> Pose {
+    let mut pose = pose.clone();
+    let (a, b, c) = (
+        pose.global_positions[chain.root],
+        pose.global_positions[chain.middle],
+        pose.global_positions[chain.end],
+    );
+    let lab = (b - a).magnitude();
+    let lcb = (c - b).magnitude();
+    if lab < 1e-9 || lcb < 1e-9 {
+        return pose;
+    }
+    let eps = 1e-6 * (lab + lcb);
+    let lat = (target - a).magnitude().clamp((lab - lcb).abs() + eps, lab + lcb - eps);
+
+    //// bend the middle joint so that the root-end distance matches the target distance
+    let mut bend_axis = (a - b).cross(c - b);
+    if bend_axis.magnitude2() < 1e-12 * lab * lab * lcb * lcb {
+        // straight limb: bend towards the pole, or around the middle joint's X axis
+        bend_axis = match pole {
+            Some(pole) => (a - b).cross(pole - b),
+            None => pose.global_rotations[chain.middle] * Position::unit_x(),
+        };
+    }
+    let bend_axis = bend_axis.normalize();
+    let angle_now = (a - b).normalize().dot((c - b).normalize()).clamp(-1.0, 1.0).acos();
+    let angle_new = ((lab * lab + lcb * lcb - lat * lat) / (2.0 * lab * lcb)).clamp(-1.0, 1.0).acos();
+    let bend = Quaternion::from_axis_angle(bend_axis, Rad(angle_new - angle_now));
+    let end_rotation = target_rotation.unwrap_or(pose.global_rotations[chain.end]);
+    let middle_rotation = bend * pose.global_rotations[chain.middle];
+    __set_global_rotation(metadata, data, &mut pose, chain.middle, middle_rotation, None);
+
+    //// swing the root so that the end points at the target
+    let c = pose.global_positions[chain.end];
+    let swing = __rotation_between(c - a, target - a);
+    let root_rotation = swing * pose.global_rotations[chain.root];
+    __set_global_rotation(metadata, data, &mut pose, chain.root, root_rotation, None);
+
+    //// twist around the root-target axis to bring the middle joint towards the pole
+    if let Some(pole) = pole {
+        let axis = (target - a).normalize();
+        let project = |p: Position| {
+            let v = p - a;
+            v - axis * v.dot(axis)
+        };
+        let (from, to) = (project(pose.global_positions[chain.middle]), project(pole));
+        if from.magnitude2() > 1e-12 && to.magnitude2() > 1e-12 {
+            let angle = from.normalize().dot(to.normalize()).clamp(-1.0, 1.0).acos();
+            let sign = if from.cross(to).dot(axis) < 0.0 { -1.0 } else { 1.0 };
+            let twist = Quaternion::from_axis_angle(axis, Rad(sign * angle));
+            let root_rotation = twist * pose.global_rotations[chain.root];
+            __set_global_rotation(metadata, data, &mut pose, chain.root, root_rotation, None);
+        }
+    }
+
+    if target_rotation.is_some() {
+        __set_global_rotation(metadata, data, &mut pose, chain.end, end_rotation, None);
+    }
+    pose
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A chain of joints from the hierarchy (parents first, each joint the child of the previous one). The last joint is the end effector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IkChain {
+    pub joints: Vec<Index>,
+    /// Optional limits for every joint of the chain.
+    pub limits: Vec<Option<AngleLimits>>,
+}
+
+impl IkChain {
+    /// Chain from `root` down to `end` (`root` has to be an ancestor of `end`).
+    pub fn from_joints(metadata: &BvhMetadata, root: Index, end: Index) -> IkChain {
+        let mut joints = vec![end];
+        while *joints.last().unwrap() != root {
+            let parent = metadata.joints[*joints.last().unwrap()].parent_index;
+            assert!(parent != -1, "Joint {} is not an ancestor of {}!", metadata.joints[root].name, metadata.joints[end].name);
+            joints.push(parent as Index);
+        }
+        joints.reverse();
+        let limits = vec![None; joints.len()];
+        IkChain { joints, limits }
+    }
+
+    /// Limit the local rotation of a joint of the chain.
+    pub fn with_limits(mut self, joint: Index, limits: AngleLimits) -> IkChain {
+        let i = self.joints.iter().position(|&j| j == joint).expect("Joint is not part of the chain!");
+        self.limits[i] = Some(limits);
+        self
+    }
+
+    fn end(&self) -> Index {
+        *self.joints.last().unwrap()
+    }
+}
+
+/// Iterative IK solvers for chains of any length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IkSolver {
+    /// Cyclic coordinate descent: rotate each joint (from the end towards the root) to point the end effector at the target.
+    Ccd { iterations: usize, tolerance: f64 },
+    /// Forward and backward reaching: solve joint positions keeping bone lengths, then rotate the bones onto them.
+    /// Angle limits are applied after every iteration (the next one continues from the clamped pose) and the closest clamped pose is kept.
+    /// The position passes don't know about the limits though, so tightly limited chains may stop short of the target - use `Ccd` for those.
+    Fabrik { iterations: usize, tolerance: f64 },
+}
+
+/// Rotate `joint` (in the pose) so that the direction from it to `point_now` becomes the direction to `point_target`.
+fn __aim(metadata: &BvhMetadata, data: &BvhData, pose: &mut Pose, joint: Index, point_now: Position, point_target: Position, limits: Option<&AngleLimits>) {
+    let origin = pose.global_positions[joint];
+    let rotation = __rotation_between(point_now - origin, point_target - origin) * pose.global_rotations[joint];
+    __set_global_rotation(metadata, data, pose, joint, rotation, limits);
+}
+
+/// Normalized vector, or zero for a zero vector (zero-length bones).
+fn __direction(v: Position) -> Position {
+    if v.magnitude2() > 1e-24 {
+        v.normalize()
+    } else {
+        Position::identity()
+    }
+}
+
+/// Solve an IK chain so that its end effector reaches `target` (and gets `target_rotation` as its global rotation, if given).
+pub fn solve_chain(
+    metadata: &BvhMetadata,
+    data: &BvhData,
+    pose: &Pose,
+    chain: &IkChain,
+    solver: IkSolver,
+    target: Position,
+    target_rotation: Option<Quaternion>,
+) -> Pose {
+    let mut pose = pose.clone();
+    let end = chain.end();
+    let n = chain.joints.len();
+    match solver {
+        IkSolver::Ccd { iterations, tolerance } => {
+            for _ in 0..iterations {
+                if (pose.global_positions[end] - target).magnitude() <= tolerance {
+                    break;
+                }
+                for i in (0..n - 1).rev() {
+                    let effector = pose.global_positions[end];
+                    __aim(metadata, data, &mut pose, chain.joints[i], effector, target, chain.limits[i].as_ref());
+                }
+            }
+        }
+        IkSolver::Fabrik { iterations, tolerance } => {
+            let lengths: Vec<f64> = chain
+                .joints
+                .windows(2)
+                .map(|j| (pose.global_positions[j[1]] - pose.global_positions[j[0]]).magnitude())
+                .collect();
+            let base = pose.global_positions[chain.joints[0]];
+            let mut best: Option<Pose> = None;
+            for _ in 0..iterations {
+                if (pose.global_positions[end] - target).magnitude() <= tolerance {
+                    break;
+                }
+                let mut positions: Vec<Position> = chain.joints.iter().map(|&j| pose.global_positions[j]).collect();
+                //// backward: from the target to the root
+                positions[n - 1] = target;
+                for i in (0..n - 1).rev() {
+                    positions[i] = positions[i + 1] + __direction(positions[i] - positions[i + 1]) * lengths[i];
+                }
+                //// forward: from the (fixed) root to the end
+                positions[0] = base;
+                for i in 0..n - 1 {
+                    positions[i + 1] = positions[i] + __direction(positions[i + 1] - positions[i]) * lengths[i];
+                }
+                //// rotate the bones onto the solved positions (clamped to the limits), the next iteration continues from the clamped pose
+                for i in 0..n - 1 {
+                    let child = pose.global_positions[chain.joints[i + 1]];
+                    __aim(metadata, data, &mut pose, chain.joints[i], child, positions[i + 1], chain.limits[i].as_ref());
+                }
+                let error = (pose.global_positions[end] - target).magnitude();
+                if !best.as_ref().is_some_and(|best| (best.global_positions[end] - target).magnitude() <= error) {
+                    best = Some(pose.clone());
+                }
+            }
+            pose = best.unwrap_or(pose);
+        }
+    }
+    if let Some(rotation) = target_rotation {
+        __set_global_rotation(metadata, data, &mut pose, end, rotation, chain.limits[n - 1].as_ref());
+    }
+    pose
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Write a pose into a frame (local rotations, global positions and rotations). The pose must belong to this skeleton.
+    pub fn set_frame_pose(&mut self, frame: usize, pose: &Pose) {
+        for joint in 0..self.pose_local_rotations.len() {
+            self.pose_local_rotations[joint][frame] = pose.local_rotations[joint];
+            self.pose_global_positions[joint][frame] = pose.global_positions[joint];
+            self.pose_global_rotations[joint][frame] = pose.global_rotations[joint];
+        }
+    }
+
+    /// Solve two-bone IK (see `solve_two_bone`) at `frame` and update the frame.
+    pub fn solve_two_bone_ik(
+        &mut self,
+        metadata: &BvhMetadata,
+        frame: usize,
+        chain: TwoBoneChain,
+        target: Position,
+        target_rotation: Option<Quaternion>,
+        pole: Option<Position>,
+    ) {
+        let pose = solve_two_bone(metadata, self, &self.pose_at_frame(frame), chain, target, target_rotation, pole);
+        self.set_frame_pose(frame, &pose);
+    }
+
+    /// Solve an IK chain (see `solve_chain`) at `frame` and update the frame.
+    pub fn solve_ik(
+        &mut self,
+        metadata: &BvhMetadata,
+        frame: usize,
+        chain: &IkChain,
+        solver: IkSolver,
+        target: Position,
+        target_rotation: Option<Quaternion>,
+    ) {
+        let pose = solve_chain(metadata, self, &self.pose_at_frame(frame), chain, solver, target, target_rotation);
+        self.set_frame_pose(frame, &pose);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::builder::SkeletonBuilder;
+use crate::reconstruct::reconstruct_from_positions;
+use crate::types::*;
+use cgmath::{InnerSpace, Zero};
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Common 3D keypoint layouts produced by pose estimation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeypointLayout {
+    /// MediaPipe Pose (BlazePose) landmarks, 33 keypoints.
+    MediaPipePose,
+    /// COCO-WholeBody, 133 keypoints (body, feet, face, hands). Face keypoints are ignored.
+    CocoWholeBody,
+    /// Human3.6M 17 joint skeleton (as used by most 3D lifting models).
+    Human36M,
+}
+
+/// Up axis convention of the keypoints. The imported animation is always Y-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordinateSystem {
+    YUp,
+    /// Image-like coordinates (Y pointing down), e.g. MediaPipe world landmarks.
+    YDown,
+    ZUp,
+}
+
+impl CoordinateSystem {
+    /// Convert a point into Y-up coordinates (keeping the handedness).
+    fn to_y_up(self, p: Position) -> Position {
+        match self {
+            CoordinateSystem::YUp => p,
+            CoordinateSystem::YDown => Position::new(p.x, -p.y, -p.z),
+            CoordinateSystem::ZUp => Position::new(p.x, p.z, -p.y),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeypointImportSettings {
+    pub coordinate_system: CoordinateSystem,
+    /// Multiplies all keypoint coordinates (e.g. 100.0 for meters -> centimeters).
+    pub scale: f64,
+    /// Time between frames in seconds.
+    pub frame_time: f64,
+}
+
+/// A joint of a skeleton template: its position is the average of `keypoints`.
+/// Leaf joints get an endsite at the average of `endsite` keypoints (or continuing their own bone if there are none).
+struct TemplateJoint {
+    name: String,
+    parent: Option<String>,
+    keypoints: Vec<Index>,
+    endsite: Option<Vec<Index>>,
+}
+
+fn __joint(name: &str, parent: Option<&str>, keypoints: &[Index], endsite: Option<&[Index]>) -> TemplateJoint {
+    TemplateJoint {
+        name: name.to_string(),
+        parent: parent.map(|p| p.to_string()),
+        keypoints: keypoints.to_vec(),
+        endsite: endsite.map(|e| e.to_vec()),
+    }
+}
+
+impl KeypointLayout {
+    pub fn num_keypoints(self) -> usize {
+        match self {
+            KeypointLayout::MediaPipePose => 33,
+            KeypointLayout::CocoWholeBody => 133,
+            KeypointLayout::Human36M => 17,
+        }
+    }
+
+    /// Import settings usually matching the layout (MediaPipe world landmarks are Y-down, Human3.6M world coordinates are Z-up).
+    pub fn default_settings(self) -> KeypointImportSettings {
+        KeypointImportSettings {
+            coordinate_system: match self {
+                KeypointLayout::MediaPipePose | KeypointLayout::CocoWholeBody => CoordinateSystem::YDown,
+                KeypointLayout::Human36M => CoordinateSystem::ZUp,
+            },
+            scale: 1.0,
+            frame_time: match self {
+                KeypointLayout::Human36M => 1.0 / 50.0,
+                _ => 1.0 / 30.0,
+            },
+        }
+    }
+
+    /// Skeleton template in depth-first order. Joint names follow the common mocap naming (Hips, LeftUpLeg, LeftForeArm, ...),
+    /// so the imported skeletons are recognized by `humanoid_mapping`.
+    fn template(self) -> Vec<TemplateJoint> {
+        let mut joints = Vec::new();
+        match self {
+            KeypointLayout::MediaPipePose => {
+                joints.push(__joint("Hips", None, &[23, 24], None));
+                joints.push(__joint("Neck", Some("Hips"), &[11, 12], None));
+                joints.push(__joint("Head", Some("Neck"), &[7, 8], Some(&[0])));
+                for (side, shoulder, elbow, wrist, fingers) in [("Left", 11, 13, 15, [17, 19]), ("Right", 12, 14, 16, [18, 20])] {
+                    joints.push(__joint(&format!("{side}Arm"), Some("Neck"), &[shoulder], None));
+                    joints.push(__joint(&format!("{side}ForeArm"), Some(&format!("{side}Arm")), &[elbow], None));
+                    joints.push(__joint(&format!("{side}Hand"), Some(&format!("{side}ForeArm")), &[wrist], Some(&fingers)));
+                }
+                for (side, hip, knee, ankle, toe) in [("Left", 23, 25, 27, 31), ("Right", 24, 26, 28, 32)] {
+                    joints.push(__joint(&format!("{side}UpLeg"), Some("Hips"), &[hip], None));
+                    joints.push(__joint(&format!("{side}Leg"), Some(&format!("{side}UpLeg")), &[knee], None));
+                    joints.push(__joint(&format!("{side}Foot"), Some(&format!("{side}Leg")), &[ankle], Some(&[toe])));
+                }
+            }
+            KeypointLayout::CocoWholeBody => {
+                joints.push(__joint("Hips", None, &[11, 12], None));
+                joints.push(__joint("Neck", Some("Hips"), &[5, 6], None));
+                joints.push(__joint("Head", Some("Neck"), &[3, 4], Some(&[0])));
+                for (side, shoulder, elbow, hand) in [("Left", 5, 7, 91), ("Right", 6, 8, 112)] {
+                    joints.push(__joint(&format!("{side}Arm"), Some("Neck"), &[shoulder], None));
+                    joints.push(__joint(&format!("{side}ForeArm"), Some(&format!("{side}Arm")), &[elbow], None));
+                    joints.push(__joint(&format!("{side}Hand"), Some(&format!("{side}ForeArm")), &[hand], None));
+                    //// hand keypoints: wrist, then 4 per finger (from the base to the tip)
+                    for (f, finger) in ["Thumb", "Index", "Middle", "Ring", "Pinky"].iter().enumerate() {
+                        let base = hand + 1 + 4 * f;
+                        let mut parent = format!("{side}Hand");
+                        for k in 0..3 {
+                            let name = format!("{side}Hand{finger}{}", k + 1);
+                            let endsite = if k == 2 { Some(vec![base + 3]) } else { None };
+                            joints.push(TemplateJoint {
+                                name: name.clone(),
+                                parent: Some(parent),
+                                keypoints: vec![base + k],
+                                endsite,
+                            });
+                            parent = name;
+                        }
+                    }
+                }
+                for (side, hip, knee, ankle, toes) in [("Left", 11, 13, 15, [17, 18]), ("Right", 12, 14, 16, [20, 21])] {
+                    joints.push(__joint(&format!("{side}UpLeg"), Some("Hips"), &[hip], None));
+                    joints.push(__joint(&format!("{side}Leg"), Some(&format!("{side}UpLeg")), &[knee], None));
+                    joints.push(__joint(&format!("{side}Foot"), Some(&format!("{side}Leg")), &[ankle], Some(&toes)));
+                }
+            }
+            KeypointLayout::Human36M => {
+                joints.push(__joint("Hips", None, &[0], None));
+                joints.push(__joint("Spine", Some("Hips"), &[7], None));
+                joints.push(__joint("Spine1", Some("Spine"), &[8], None));
+                joints.push(__joint("Neck", Some("Spine1"), &[9], None));
+                joints.push(__joint("Head", Some("Neck"), &[10], None));
+                for (side, shoulder, elbow, wrist) in [("Left", 11, 12, 13), ("Right", 14, 15, 16)] {
+                    joints.push(__joint(&format!("{side}Arm"), Some("Spine1"), &[shoulder], None));
+                    joints.push(__joint(&format!("{side}ForeArm"), Some(&format!("{side}Arm")), &[elbow], None));
+                    joints.push(__joint(&format!("{side}Hand"), Some(&format!("{side}ForeArm")), &[wrist], None));
+                }
+                for (side, hip, knee, ankle) in [("Left", 4, 5, 6), ("Right", 1, 2, 3)] {
+                    joints.push(__joint(&format!("{side}UpLeg"), Some("Hips"), &[hip], None));
+                    joints.push(__joint(&format!("{side}Leg"), Some(&format!("{side}UpLeg")), &[knee], None));
+                    joints.push(__joint(&format!("{side}Foot"), Some(&format!("{side}Leg")), &[ankle], None));
+                }
+            }
+        }
+        joints
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn __median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    values[values.len() / 2]
+}
+
+/// Rest offset of a bone measured over all frames where both of its joints were detected: median length along the average direction.
+fn __estimate_offset(from: &[Option<Position>], to: &[Option<Position>]) -> Position {
+    let bones: Vec<Position> = from
+        .iter()
+        .zip(to.iter())
+        .filter_map(|(a, b)| Some(b.as_ref()? - a.as_ref()?))
+        .filter(|v| v.magnitude2() > 1e-24)
+        .collect();
+    let length = __median(bones.iter().map(|v| v.magnitude()).collect());
+    let direction: Position = bones.iter().map(|v| v.normalize()).sum();
+    if direction.magnitude2() < 1e-24 {
+        return Position::new(0.0, length, 0.0);
+    }
+    direction.normalize() * length
+}
+
+/// Average of the given keypoints of every frame, `None` where any of them wasn't detected.
+fn __average_keypoints(frames: &[Vec<Option<Position>>], keypoints: &[Index]) -> Vec<Option<Position>> {
+    frames
+        .iter()
+        .map(|frame| Some(keypoints.iter().map(|&k| frame[k]).sum::<Option<Position>>()? / keypoints.len() as f64))
+        .collect()
+}
+
+/// Import keypoints (`[frame][keypoint]`, in the order of the layout) as an animated skeleton.
+///
+/// The skeleton comes from the layout's template, joint offsets are estimated from median bone lengths over all frames
+/// (pointing in the average bone direction), rotations and root translation are reconstructed with `reconstruct_from_positions`.
+///
+/// Keypoints at exactly `(0, 0, 0)` are treated as undetected (that's how most pose estimators report them). A joint which wasn't
+/// detected, or which coincides with its parent, keeps the bone direction of the previous frame (or of the rest pose if it wasn't
+/// detected yet), so its rotation is held. An undetected root keeps its previous position.
+pub fn import_keypoints(layout: KeypointLayout, frames: &[Vec<Position>], settings: &KeypointImportSettings) -> (BvhMetadata, BvhData) {
+    let num_keypoints = layout.num_keypoints();
+    assert!(!frames.is_empty(), "No keypoint frames to import!");
+    for frame in frames.iter() {
+        assert_eq!(frame.len(), num_keypoints, "Frame has different number of keypoints than the layout ({:?})!", layout);
+    }
+    let frames: Vec<Vec<Option<Position>>> = frames
+        .iter()
+        .map(|frame| {
+            frame
+                .iter()
+                .map(|&p| (p != Position::zero()).then(|| settings.coordinate_system.to_y_up(p) * settings.scale))
+                .collect()
+        })
+        .collect();
+
+    //// detected joint positions `[joint][frame]`
+    let template = layout.template();
+    let mut detected: Vec<Vec<Option<Position>>> = template.iter().map(|joint| __average_keypoints(&frames, &joint.keypoints)).collect();
+    // an undetected root keeps its previous position (root-relative keypoints have the root at the origin in every frame)
+    let mut root = detected[0].iter().find_map(|p| *p).unwrap_or(Position::zero());
+    for position in detected[0].iter_mut() {
+        root = position.unwrap_or(root);
+        *position = Some(root);
+    }
+    let index_of = |name: &str| template.iter().position(|joint| joint.name == name).unwrap();
+    let parents: Vec<Option<Index>> = template.iter().map(|joint| joint.parent.as_deref().map(index_of)).collect();
+
+    //// skeleton with estimated offsets
+    let mut builder = SkeletonBuilder::new(&template[0].name, Position::zero());
+    let mut offsets = vec![Position::zero(); template.len()];
+    for (i, joint) in template.iter().enumerate().skip(1) {
+        let parent = joint.parent.as_ref().expect("Only the first template joint can be the root!");
+        offsets[i] = __estimate_offset(&detected[index_of(parent)], &detected[i]);
+        builder = builder.joint(&joint.name, parent, offsets[i]);
+    }
+    for (i, joint) in template.iter().enumerate() {
+        let has_children = template.iter().any(|j| j.parent.as_deref() == Some(joint.name.as_str()));
+        if has_children {
+            continue;
+        }
+        let offset = match &joint.endsite {
+            Some(keypoints) => __estimate_offset(&detected[i], &__average_keypoints(&frames, keypoints)),
+            None => offsets[i],
+        };
+        builder = builder.endsite(&joint.name, offset);
+    }
+    let (metadata, data) = builder.animation(settings.frame_time).build();
+
+    //// fill in undetected joints (template joints are ordered parents first)
+    let mut positions = vec![Vec::with_capacity(frames.len()); template.len()];
+    positions[0] = detected[0].iter().map(|p| p.unwrap()).collect();
+    let mut held_bones = offsets;
+    for frame in 0..frames.len() {
+        for i in 1..template.len() {
+            let parent = parents[i].unwrap();
+            if let (Some(from), Some(to)) = (detected[parent][frame], detected[i][frame]) {
+                if (to - from).magnitude2() > 1e-24 {
+                    held_bones[i] = to - from;
+                }
+            }
+            let position = positions[parent][frame] + held_bones[i];
+            positions[i].push(position);
+        }
+    }
+
+    reconstruct_from_positions(&metadata, &data.rest_local_positions, &positions)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Parse a keypoint (`[x, y, z, ...]` or `{"x": .., "y": .., "z": ..}`) or a list of keypoints (nested or flat `[x, y, z, x, y, z, ...]`).
+fn __parse_json_keypoints(value: &serde_json::Value) -> Vec<Position> {
+    use serde_json::Value;
+    let number = |v: &Value| v.as_f64().expect("Keypoint coordinate is not a number");
+    match value {
+        Value::Object(object) => {
+            // a frame wrapped in an object (e.g. {"keypoints": [...]}, {"landmarks": [...]}), or a single keypoint
+            if let Some(inner) = ["keypoints", "landmarks", "joints"].iter().find_map(|key| object.get(*key)) {
+                return __parse_json_keypoints(inner);
+            }
+            vec![Position::new(number(&object["x"]), number(&object["y"]), number(&object["z"]))]
+        }
+        Value::Array(items) if items.iter().all(|item| item.is_number()) => {
+            items.chunks_exact(3).map(|c| Position::new(number(&c[0]), number(&c[1]), number(&c[2]))).collect()
+        }
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                // [x, y, z] or [x, y, z, visibility/confidence]
+                Value::Array(c) => Position::new(number(&c[0]), number(&c[1]), number(&c[2])),
+                other => __parse_json_keypoints(other)[0],
+            })
+            .collect(),
+        _ => panic!("Unexpected keypoint value: {}", value),
+    }
+}
+
+/// Parse keypoint frames from JSON: a list of frames (or an object with a "frames" list), each frame being a list of keypoints
+/// (`[x, y, z]`, `{"x": .., "y": .., "z": ..}` or a flat list of coordinates) or an object with a "keypoints"/"landmarks" list.
+/// Returns `[frame][keypoint]`.
+pub fn parse_keypoints_json(json: &str) -> Vec<Vec<Position>> {
+    let value: serde_json::Value = serde_json::from_str(json).expect("Error parsing keypoints JSON");
+    let frames = match &value {
+        serde_json::Value::Object(object) => object.get("frames").expect("JSON object has no \"frames\" field"),
+        other => other,
+    };
+    frames
+        .as_array()
+        .expect("Keypoint frames have to be a list")
+        .iter()
+        .map(__parse_json_keypoints)
+        .collect()
+}
+
+/// Parse keypoint frames from CSV: one row per frame with `x, y, z` (or `x, y, z, visibility`) for every keypoint,
+/// optionally starting with a frame index column. A header in the first line is skipped, any other row that isn't
+/// numeric panics. Returns `[frame][keypoint]`.
+pub fn parse_keypoints_csv(csv: &str, num_keypoints: usize) -> Vec<Vec<Position>> {
+    csv.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(line_index, line)| {
+            match line.split(',').map(|value| value.trim().parse::<f64>()).collect::<Result<Vec<f64>, _>>() {
+                Ok(values) => Some((line_index, values)),
+                Err(_) if line_index == 0 => None,
+                Err(error) => panic!("CSV line {} has a non-numeric value: {}", line_index + 1, error),
+            }
+        })
+        .map(|(line_index, values)| {
+            let (values, stride) = match values.len() {
+                n if n == 3 * num_keypoints => (&values[..], 3),
+                n if n == 3 * num_keypoints + 1 => (&values[1..], 3),
+                n if n == 4 * num_keypoints => (&values[..], 4),
+                n if n == 4 * num_keypoints + 1 => (&values[1..], 4),
+                n => panic!("CSV line {} has {} values, which doesn't match {} keypoints", line_index + 1, n, num_keypoints),
+            };
+            values.chunks_exact(stride).map(|c| Position::new(c[0], c[1], c[2])).collect()
+        })
+        .collect()
+}
+
+/// Load a .json or .csv keypoint file (see `parse_keypoints_json`, `parse_keypoints_csv`) and import it (see `import_keypoints`).
+pub fn load_keypoints_from_file(file_path: &str, layout: KeypointLayout, settings: &KeypointImportSettings) -> (BvhMetadata, BvhData) {
+    let contents = std::fs::read_to_string(file_path).expect("Error reading file");
+    let frames = if file_path.to_lowercase().ends_with(".csv") {
+        parse_keypoints_csv(&contents, layout.num_keypoints())
+    } else {
+        parse_keypoints_json(&contents)
+    };
+    import_keypoints(layout, &frames, settings)
+}
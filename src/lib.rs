@@ -2,6 +2,30 @@
 pub mod types;
 pub mod parse;
 pub mod utils;
+pub mod units;
+pub mod sample;
+pub mod resample;
+pub mod clip;
+pub mod blend;
+pub mod mask;
+pub mod mirror;
+pub mod root_motion;
+pub mod encoding;
+pub mod retarget;
+pub mod humanoid;
+pub mod autolabel;
+pub mod skeleton;
+pub mod builder;
+pub mod rest_pose;
+pub mod derivatives;
+pub mod filter;
+pub mod contact;
+pub mod ik;
+pub mod foot_skate;
+pub mod reconstruct;
+pub mod keypoints;
+pub mod compress;
+pub mod looping;
 
 
 #[cfg(feature = "visualize")]
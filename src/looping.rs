@@ -0,0 +1,181 @@
+use crate::blend::__smoothstep;
+use crate::clip::RootAlignment;
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use crate::utils::{__same_hemisphere, __twist_about_axis};
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Constraints of loop detection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopSettings {
+    /// Minimal number of frames of the loop.
+    pub min_length: usize,
+    /// Maximal number of frames of the loop (`None` = up to the whole animation).
+    pub max_length: Option<usize>,
+    /// Weight of joint velocity differences (in units per second) relative to joint position differences.
+    /// Matching velocities avoids loops which match in pose but not in motion (e.g. a swing going back and forth).
+    pub velocity_weight: f64,
+}
+
+/// A loop of the animation: frame `end` is (nearly) the same pose as frame `start`, so frames `start..end` can be played in a loop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopCandidate {
+    pub start: usize,
+    pub end: usize,
+    /// Pose distance between `start` and `end` (see `pose_distance_matrix`).
+    pub cost: f64,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Joint positions relative to the root, in the root's heading frame (root translation and rotation around Y removed), `[frame][joint]`.
+fn __root_relative_positions(metadata: &BvhMetadata, data: &BvhData) -> Vec<Vec<Position>> {
+    (0..metadata.num_frames)
+        .map(|f| {
+            let root = data.pose_global_positions[0][f];
+            let heading = __twist_about_axis(data.pose_global_rotations[0][f], Position::unit_y()).conjugate();
+            (0..metadata.joints.len())
+                .map(|j| heading * (data.pose_global_positions[j][f] - root))
+                .collect()
+        })
+        .collect()
+}
+
+/// Velocities of the root-relative positions (units per second), with the root's own velocity (in its heading frame) as the first entry.
+fn __root_relative_velocities(metadata: &BvhMetadata, data: &BvhData, positions: &[Vec<Position>]) -> Vec<Vec<Position>> {
+    let n = metadata.num_frames;
+    (0..n)
+        .map(|f| {
+            if n < 2 {
+                return vec![Position::identity(); metadata.joints.len() + 1];
+            }
+            // forward difference (the last frame repeats the previous one)
+            let (a, b) = if f + 1 < n { (f, f + 1) } else { (f - 1, f) };
+            let heading = __twist_about_axis(data.pose_global_rotations[0][a], Position::unit_y()).conjugate();
+            let root_velocity = heading * (data.pose_global_positions[0][b] - data.pose_global_positions[0][a]) / metadata.frame_time;
+            std::iter::once(root_velocity)
+                .chain(positions[a].iter().zip(positions[b].iter()).map(|(pa, pb)| (pb - pa) / metadata.frame_time))
+                .collect()
+        })
+        .collect()
+}
+
+fn __mean_squared_distance(a: &[Position], b: &[Position]) -> f64 {
+    a.iter().zip(b.iter()).map(|(pa, pb)| (pa - pb).magnitude2()).sum::<f64>() / a.len().max(1) as f64
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Distances between the poses of all pairs of frames, `[frame][frame]`.
+    ///
+    /// Poses are compared independently of where the character is and which way it faces (root translation and heading are removed):
+    /// `sqrt(mean squared joint position difference + velocity_weight * mean squared joint velocity difference)`.
+    pub fn pose_distance_matrix(&self, metadata: &BvhMetadata, velocity_weight: f64) -> Vec<Vec<f64>> {
+        let positions = __root_relative_positions(metadata, self);
+        let velocities = __root_relative_velocities(metadata, self, &positions);
+        (0..metadata.num_frames)
+            .map(|i| {
+                (0..metadata.num_frames)
+                    .map(|j| {
+                        let position_distance = __mean_squared_distance(&positions[i], &positions[j]);
+                        let velocity_distance = __mean_squared_distance(&velocities[i], &velocities[j]);
+                        (position_distance + velocity_weight * velocity_distance).sqrt()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Find the best loop: the pair of frames with the lowest pose distance (see `pose_distance_matrix`) which are at least
+    /// `settings.min_length` frames apart. Returns `None` if the animation is too short.
+    pub fn find_loop(&self, metadata: &BvhMetadata, settings: &LoopSettings) -> Option<LoopCandidate> {
+        let distances = self.pose_distance_matrix(metadata, settings.velocity_weight);
+        let min_length = settings.min_length.max(1);
+        let max_length = settings.max_length.unwrap_or(usize::MAX);
+        let mut best: Option<LoopCandidate> = None;
+        for (start, row) in distances.iter().enumerate() {
+            for (end, &cost) in row.iter().enumerate().skip(start + min_length) {
+                if end - start > max_length {
+                    break;
+                }
+                if !best.is_some_and(|best| best.cost <= cost) {
+                    best = Some(LoopCandidate { start, end, cost });
+                }
+            }
+        }
+        best
+    }
+
+    /// Cut the loop `start..end` out of the animation and blend away the difference between the poses of `end` and `start`
+    /// over the last `blend_frames` frames, so that the clip loops without popping.
+    ///
+    /// The returned clip has `end - start + 1` frames: its last frame is its first pose again, placed where the root continues to
+    /// (the root keeps its translation and turning over the cycle, only the height and the tilt are blended), so the root moves on
+    /// with every cycle. Use `repeat_loop` to play it several times.
+    pub fn make_loop(&self, metadata: &BvhMetadata, start: usize, end: usize, blend_frames: usize) -> (BvhMetadata, BvhData) {
+        assert!(
+            start < end && end < metadata.num_frames,
+            "Loop {}..{} out of bounds (animation has {} frames)!",
+            start,
+            end,
+            metadata.num_frames
+        );
+        let length = end - start;
+        let blend_frames = blend_frames.clamp(1, length);
+
+        //// the start pose placed at the end of the cycle: same position on the ground plane and heading as frame `end`
+        let root_start = self.pose_local_rotations[0][start];
+        let root_end = self.pose_local_rotations[0][end];
+        let heading = __twist_about_axis(root_end * root_start.conjugate(), Position::unit_y());
+        let position_delta = Position::new(0.0, self.pose_global_positions[0][start].y - self.pose_global_positions[0][end].y, 0.0);
+        let rotation_deltas: Vec<Quaternion> = self
+            .pose_local_rotations
+            .iter()
+            .enumerate()
+            .map(|(j, track)| {
+                let target = if j == 0 { heading * root_start } else { track[start] };
+                __same_hemisphere(target * track[end].conjugate(), Quaternion::identity())
+            })
+            .collect();
+
+        //// spread the differences over the end of the clip (fully applied at its last frame)
+        let weight = |k: usize| __smoothstep((k as f64 - (length - blend_frames) as f64) / blend_frames as f64);
+        let root_positions = (0..=length)
+            .map(|k| self.pose_global_positions[0][start + k] + position_delta * weight(k))
+            .collect();
+        let local_rotations = self
+            .pose_local_rotations
+            .iter()
+            .zip(rotation_deltas.iter())
+            .map(|(track, &delta)| {
+                (0..=length)
+                    .map(|k| Quaternion::identity().slerp(delta, weight(k)) * track[start + k])
+                    .collect()
+            })
+            .collect();
+
+        let new_metadata = BvhMetadata {
+            num_frames: length + 1,
+            ..metadata.clone()
+        };
+        let new_data = __build_bvh_data(&new_metadata, self, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+
+    /// Play a loop made by `make_loop` `times` times. The cycles share their first and last frame, so it is played only
+    /// once between them, and the root keeps moving and turning without stopping at the seams.
+    pub fn repeat_loop(&self, metadata: &BvhMetadata, times: usize) -> (BvhMetadata, BvhData) {
+        assert!(metadata.num_frames >= 2, "Loop has to have at least 2 frames!");
+        if times == 0 {
+            return self.slice(metadata, 0..0);
+        }
+        let last = metadata.num_frames - 1;
+        let (cycle_metadata, cycle_data) = self.slice(metadata, 0..last);
+        let (end_metadata, end_data) = self.slice(metadata, last..metadata.num_frames);
+        let (repeated_metadata, repeated_data) = cycle_data.repeat(&cycle_metadata, times, RootAlignment::PositionAndHeading);
+        repeated_data.concat(&repeated_metadata, &end_data, &end_metadata, RootAlignment::PositionAndHeading)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Per-joint weights (0.0 - 1.0) used for masked blending and additive layers.
+/// The root translation follows the weight of the root joint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JointMask {
+    pub weights: Vec<f64>,
+}
+
+impl JointMask {
+    /// Mask with the same weight for every joint.
+    pub fn uniform(metadata: &BvhMetadata, weight: f64) -> JointMask {
+        JointMask {
+            weights: vec![weight; metadata.joints.len()],
+        }
+    }
+
+    /// Mask with weight 1.0 for the joint `name` and all of its descendants (e.g. upper body = subtree of `Spine2`), 0.0 elsewhere.
+    pub fn subtree(metadata: &BvhMetadata, name: &str) -> JointMask {
+        JointMask::subtree_with_falloff(metadata, name, 0)
+    }
+
+    /// Like `subtree`, but the weight ramps up over the first `falloff` levels of the subtree,
+    /// e.g. with `falloff = 2` the weights are 1/3, 2/3, 1, 1, ... going down the hierarchy. Softens the seam of masked blends.
+    pub fn subtree_with_falloff(metadata: &BvhMetadata, name: &str, falloff: usize) -> JointMask {
+        let subtree_root = metadata.find_joint_by_name(name);
+        let mut mask = JointMask::uniform(metadata, 0.0);
+        for index in metadata.get_subtree(subtree_root.index) {
+            let level = metadata.joints[index].depth - subtree_root.depth;
+            mask.weights[index] = ((level + 1) as f64 / (falloff + 1) as f64).min(1.0);
+        }
+        mask
+    }
+
+    /// Mask with weight 1.0 for the joints with given names, 0.0 elsewhere.
+    pub fn from_names(metadata: &BvhMetadata, names: &[&str]) -> JointMask {
+        let mut mask = JointMask::uniform(metadata, 0.0);
+        for name in names {
+            mask.weights[metadata.find_joint_by_name(name).index] = 1.0;
+        }
+        mask
+    }
+
+    /// Set the weight of a single joint.
+    pub fn set_weight(&mut self, index: Index, weight: f64) -> &mut Self {
+        self.weights[index] = weight;
+        self
+    }
+
+    /// Weight of a joint.
+    pub fn weight(&self, index: Index) -> f64 {
+        self.weights[index]
+    }
+
+    /// `1 - weight` for every joint (e.g. lower body = inverted upper body mask).
+    pub fn inverted(&self) -> JointMask {
+        JointMask {
+            weights: self.weights.iter().map(|w| 1.0 - w).collect(),
+        }
+    }
+
+    /// Multiply every weight by `factor`.
+    pub fn scaled(&self, factor: f64) -> JointMask {
+        JointMask {
+            weights: self.weights.iter().map(|w| w * factor).collect(),
+        }
+    }
+}
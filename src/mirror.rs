@@ -0,0 +1,282 @@
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use crate::utils;
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Axis that gets negated by mirroring (i.e. the normal of the mirror plane). For Y-up characters facing Z it's usually `X`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl MirrorAxis {
+    fn normal(self) -> Position {
+        match self {
+            MirrorAxis::X => Position::unit_x(),
+            MirrorAxis::Y => Position::unit_y(),
+            MirrorAxis::Z => Position::unit_z(),
+        }
+    }
+
+    /// Reflect a position.
+    fn reflect_position(self, p: Position) -> Position {
+        let n = self.normal();
+        p - n * (2.0 * p.dot(n))
+    }
+
+    /// Reflect a rotation (`M * R * M` where `M` is the reflection matrix).
+    fn reflect_rotation(self, q: Quaternion) -> Quaternion {
+        Quaternion::from_sv(q.s, -self.reflect_position(q.v))
+    }
+}
+
+/// Side of the body a joint is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// One rule of pairing left joint names with right joint names (the left pattern goes first).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameRule {
+    /// Swap the substrings anywhere in the name (e.g. `Left` <-> `Right` in `LeftUpLeg`).
+    Substring(String, String),
+    /// Swap the prefixes (e.g. `L_` <-> `R_` in `L_Knee`).
+    Prefix(String, String),
+    /// Swap the suffixes (e.g. `.L` <-> `.R` in `upper_arm.L`).
+    Suffix(String, String),
+}
+
+/// Rules used to find the mirrored counterpart of a joint by its name. The first matching rule wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamePairing {
+    pub rules: Vec<NameRule>,
+}
+
+impl Default for NamePairing {
+    /// Left/Right, left/right, LEFT/RIGHT, L_/R_, l_/r_, _L/_R, _l/_r, .L/.R and .l/.r
+    fn default() -> Self {
+        let s = |a: &str, b: &str| (a.to_string(), b.to_string());
+        let substring = |(a, b)| NameRule::Substring(a, b);
+        let prefix = |(a, b)| NameRule::Prefix(a, b);
+        let suffix = |(a, b)| NameRule::Suffix(a, b);
+        NamePairing {
+            rules: vec![
+                substring(s("Left", "Right")),
+                substring(s("left", "right")),
+                substring(s("LEFT", "RIGHT")),
+                prefix(s("L_", "R_")),
+                prefix(s("l_", "r_")),
+                suffix(s("_L", "_R")),
+                suffix(s("_l", "_r")),
+                suffix(s(".L", ".R")),
+                suffix(s(".l", ".r")),
+            ],
+        }
+    }
+}
+
+impl NamePairing {
+    /// Side of the body the joint is on according to the first matching rule, or `None` if no rule matches.
+    pub fn side(&self, name: &str) -> Option<Side> {
+        for rule in self.rules.iter() {
+            let matches = |pattern: &String| match rule {
+                NameRule::Substring(..) => name.contains(pattern.as_str()),
+                NameRule::Prefix(..) => name.starts_with(pattern.as_str()),
+                NameRule::Suffix(..) => name.ends_with(pattern.as_str()),
+            };
+            let (left, right) = match rule {
+                NameRule::Substring(a, b) | NameRule::Prefix(a, b) | NameRule::Suffix(a, b) => (a, b),
+            };
+            if matches(left) {
+                return Some(Side::Left);
+            }
+            if matches(right) {
+                return Some(Side::Right);
+            }
+        }
+        None
+    }
+
+    /// Name of the mirrored counterpart, or `None` if no rule matches (i.e. the joint lies on the mirror plane).
+    pub fn mirror_name(&self, name: &str) -> Option<String> {
+        for rule in self.rules.iter() {
+            let (a, b) = match rule {
+                NameRule::Substring(a, b) | NameRule::Prefix(a, b) | NameRule::Suffix(a, b) => (a, b),
+            };
+            for (from, to) in [(a, b), (b, a)] {
+                let mirrored = match rule {
+                    NameRule::Substring(..) if name.contains(from.as_str()) => Some(name.replace(from.as_str(), to)),
+                    NameRule::Prefix(..) => name.strip_prefix(from.as_str()).map(|rest| format!("{}{}", to, rest)),
+                    NameRule::Suffix(..) => name.strip_suffix(from.as_str()).map(|rest| format!("{}{}", rest, to)),
+                    _ => None,
+                };
+                if mirrored.is_some() {
+                    return mirrored;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Why a skeleton can't be mirrored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MirrorError {
+    /// Name of the joint matched a pairing rule, but there's no joint with the mirrored name.
+    MissingCounterpart { joint: String, counterpart: String },
+    /// Mirrored joints have parents which aren't mirrored counterparts of each other.
+    ParentMismatch { joint: String, counterpart: String },
+    /// Offsets of the joint's children (and End Site) differ from the mirrored counterpart's ones more than allowed (relative error).
+    Asymmetric { joint: String, counterpart: String, error: f64 },
+}
+
+/// Default maximal relative difference between mirrored bone offsets.
+pub const DEFAULT_SYMMETRY_TOLERANCE: f64 = 0.05;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Rest frame correction of every joint: rotation mapping the joint's child offsets (and End Site) onto the reflected offsets of its counterpart.
+/// Identity for skeletons whose offsets are symmetric, but e.g. a 180 degrees rotation for rigs where both arms point along +X in their local frames.
+fn __mirror_corrections(
+    metadata: &BvhMetadata,
+    data: &BvhData,
+    axis: MirrorAxis,
+    pairs: &[Index],
+    tolerance: f64,
+) -> Result<Vec<Quaternion>, MirrorError> {
+    let mut corrections = vec![Quaternion::identity(); metadata.joints.len()];
+    for joint in metadata.joints.iter() {
+        let counterpart = &metadata.joints[pairs[joint.index]];
+        let mut from: Vec<Position> = joint.children.iter().map(|&c| data.rest_local_positions[c]).collect();
+        let mut to: Vec<Position> = joint
+            .children
+            .iter()
+            .map(|&c| axis.reflect_position(data.rest_local_positions[pairs[c]]))
+            .collect();
+        if let (Some(a), Some(b)) = (&joint.endsite, &counterpart.endsite) {
+            from.push(a.offset);
+            to.push(axis.reflect_position(b.offset));
+        }
+
+        let parent_correction = if joint.parent_index == -1 {
+            Quaternion::identity()
+        } else {
+            corrections[joint.parent_index as Index]
+        };
+        let correction = utils::__fit_rotation(&from, &to, parent_correction).unwrap_or(parent_correction);
+
+        //// validate that the counterpart's offsets really are a mirror image
+        for (a, b) in from.iter().zip(to.iter()) {
+            let error = (correction * a - b).magnitude() / a.magnitude().max(b.magnitude()).max(1e-9);
+            if error > tolerance {
+                return Err(MirrorError::Asymmetric {
+                    joint: joint.name.clone(),
+                    counterpart: counterpart.name.clone(),
+                    error,
+                });
+            }
+        }
+        corrections[joint.index] = correction;
+    }
+    Ok(corrections)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhMetadata {
+    /// Index of the mirrored counterpart of every joint (joints lying on the mirror plane map to themselves).
+    pub fn mirror_pairs(&self, pairing: &NamePairing) -> Result<Vec<Index>, MirrorError> {
+        let pairs = self
+            .joints
+            .iter()
+            .map(|joint| match pairing.mirror_name(&joint.name) {
+                None => Ok(joint.index),
+                Some(counterpart) => self
+                    .joints
+                    .iter()
+                    .find(|j| j.name == counterpart)
+                    .map(|j| j.index)
+                    .ok_or(MirrorError::MissingCounterpart {
+                        joint: joint.name.clone(),
+                        counterpart,
+                    }),
+            })
+            .collect::<Result<Vec<Index>, MirrorError>>()?;
+
+        for joint in self.joints.iter() {
+            let counterpart = &self.joints[pairs[joint.index]];
+            let parents_match = match (joint.parent_index, counterpart.parent_index) {
+                (-1, -1) => true,
+                (-1, _) | (_, -1) => false,
+                (a, b) => pairs[a as Index] == b as Index,
+            };
+            if !parents_match {
+                return Err(MirrorError::ParentMismatch {
+                    joint: joint.name.clone(),
+                    counterpart: counterpart.name.clone(),
+                });
+            }
+        }
+        Ok(pairs)
+    }
+}
+
+impl BvhData {
+    /// Mirror the animation: swap left/right joint tracks, reflect the root translation and reflect rotations (respecting each joint's rest frame).
+    /// Fails if the skeleton isn't symmetric (within `DEFAULT_SYMMETRY_TOLERANCE`).
+    pub fn mirror(&self, metadata: &BvhMetadata, axis: MirrorAxis, pairing: &NamePairing) -> Result<BvhData, MirrorError> {
+        self.mirror_with_tolerance(metadata, axis, pairing, DEFAULT_SYMMETRY_TOLERANCE)
+    }
+
+    /// Same as `mirror`, with a custom maximal relative difference between mirrored bone offsets.
+    pub fn mirror_with_tolerance(
+        &self,
+        metadata: &BvhMetadata,
+        axis: MirrorAxis,
+        pairing: &NamePairing,
+        tolerance: f64,
+    ) -> Result<BvhData, MirrorError> {
+        let pairs = metadata.mirror_pairs(pairing)?;
+        let corrections = __mirror_corrections(metadata, self, axis, &pairs, tolerance)?;
+
+        //// mirrored global rotation: G'_j = M * G_pair(j) * M * C_j
+        let global_rotations: Vec<Vec<Quaternion>> = metadata
+            .joints
+            .iter()
+            .map(|joint| {
+                self.pose_global_rotations[pairs[joint.index]]
+                    .iter()
+                    .map(|&q| axis.reflect_rotation(q) * corrections[joint.index])
+                    .collect()
+            })
+            .collect();
+
+        //// back to local rotations
+        let local_rotations = metadata
+            .joints
+            .iter()
+            .map(|joint| {
+                if joint.parent_index == -1 {
+                    return global_rotations[joint.index].clone();
+                }
+                global_rotations[joint.parent_index as Index]
+                    .iter()
+                    .zip(global_rotations[joint.index].iter())
+                    .map(|(parent, child)| parent.conjugate() * child)
+                    .collect()
+            })
+            .collect();
+        let root_positions = self.pose_global_positions[pairs[0]]
+            .iter()
+            .map(|&p| axis.reflect_position(p))
+            .collect();
+
+        Ok(__build_bvh_data(metadata, self, root_positions, local_rotations))
+    }
+}
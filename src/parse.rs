@@ -57,7 +57,7 @@ fn __get_tail_offset(joint: &Joint, offsets: &Vec<Position>) -> Position {
 }
 
 /// Calculate the global rest pose of a joint.
-fn __calc_rest_pose(bvh: &BvhMetadata, data: &mut BvhData) {
+pub(crate) fn __calc_rest_pose(bvh: &BvhMetadata, data: &mut BvhData) {
     for joint in bvh.joints.iter() {
         //// CALCULATE REST GLOBAL POSITIONS
         data.rest_global_positions[joint.index] = if joint.parent_index != -1 {
@@ -114,7 +114,7 @@ fn __calc_rest_pose(bvh: &BvhMetadata, data: &mut BvhData) {
 }
 
 /// Calculate the global pose position of a joint. Basically forward kinematics.
-fn __calc_pose(bvh: &BvhMetadata, data: &mut BvhData) {
+pub(crate) fn __calc_pose(bvh: &BvhMetadata, data: &mut BvhData) {
     fn ____recursive_transform(
         joint_index: Index,
         frame: usize,
@@ -160,6 +160,83 @@ fn __calc_pose(bvh: &BvhMetadata, data: &mut BvhData) {
     }
 }
 
+/// Create `BvhData` for new animation frames (root positions and local rotations) of an already parsed skeleton.
+/// Rest pose is copied from `data`, global pose is calculated with forward kinematics.
+/// `metadata.num_frames` must match the number of the new frames.
+pub(crate) fn __build_bvh_data(
+    metadata: &BvhMetadata,
+    data: &BvhData,
+    root_positions: Vec<Position>,
+    pose_local_rotations: Vec<Vec<Quaternion>>,
+) -> BvhData {
+    let num_joints = metadata.joints.len();
+    let num_frames = metadata.num_frames;
+    let mut pose_global_positions = vec![vec![Position::identity(); num_frames]; num_joints];
+    pose_global_positions[0] = root_positions;
+
+    let mut new_data = BvhData {
+        rest_local_positions: data.rest_local_positions.clone(),
+        rest_local_rotations: data.rest_local_rotations.clone(),
+        rest_global_positions: data.rest_global_positions.clone(),
+        rest_global_rotations: data.rest_global_rotations.clone(),
+        pose_global_positions,
+        pose_global_rotations: vec![vec![Quaternion::identity(); num_frames]; num_joints],
+        pose_local_rotations,
+        pose_local_positions: vec![vec![Position::identity(); num_frames]; num_joints],
+    };
+    __calc_pose(metadata, &mut new_data);
+    new_data
+}
+
+/// Create `BvhData` for a new (or edited) skeleton from its rest offsets and animation frames.
+/// Rest pose and global pose are calculated the same way as when parsing a .bvh file.
+pub(crate) fn __build_bvh_data_with_rest(
+    metadata: &BvhMetadata,
+    rest_local_positions: Vec<Position>,
+    root_positions: Vec<Position>,
+    pose_local_rotations: Vec<Vec<Quaternion>>,
+) -> BvhData {
+    let num_joints = metadata.joints.len();
+    let mut rest = BvhData {
+        rest_local_positions,
+        rest_local_rotations: vec![Quaternion::identity(); num_joints],
+        rest_global_positions: vec![Position::identity(); num_joints],
+        rest_global_rotations: vec![Quaternion::identity(); num_joints],
+        pose_global_positions: vec![Vec::new(); num_joints],
+        pose_global_rotations: vec![Vec::new(); num_joints],
+        pose_local_rotations: vec![Vec::new(); num_joints],
+        pose_local_positions: vec![Vec::new(); num_joints],
+    };
+    __calc_rest_pose(metadata, &mut rest);
+    __build_bvh_data(metadata, &rest, root_positions, pose_local_rotations)
+}
+
+/// Forward kinematics of a single pose (not stored in `BvhData`). Returns global positions and rotations of every joint.
+/// Joints are assumed to be ordered parents-first (which is always the case for parsed files).
+pub(crate) fn __calc_global_pose(
+    metadata: &BvhMetadata,
+    rest_local_positions: &[Position],
+    local_rotations: &[Quaternion],
+    root_position: Position,
+) -> (Vec<Position>, Vec<Quaternion>) {
+    let num_joints = metadata.joints.len();
+    let mut global_positions = vec![Position::identity(); num_joints];
+    let mut global_rotations = vec![Quaternion::identity(); num_joints];
+    for joint in metadata.joints.iter() {
+        let i = joint.index;
+        if joint.parent_index == -1 {
+            global_positions[i] = root_position;
+            global_rotations[i] = local_rotations[i];
+        } else {
+            let parent = joint.parent_index as Index;
+            global_positions[i] =
+                global_positions[parent] + global_rotations[parent] * rest_local_positions[i];
+            global_rotations[i] = global_rotations[parent] * local_rotations[i];
+        }
+    }
+    (global_positions, global_rotations)
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 
 fn parse_bvh(lines: Lines) -> (BvhMetadata, BvhData) {
@@ -178,7 +255,8 @@ fn parse_bvh(lines: Lines) -> (BvhMetadata, BvhData) {
     let mut channels_index = 0;
     let mut depth: Depth = 0;
 
-    let re_joint = Regex::new(r"(ROOT|JOINT) (\w+)").unwrap();
+    // joint names can contain characters like ':' (e.g. Mixamo's "mixamorig:Hips")
+    let re_joint = Regex::new(r"(ROOT|JOINT)\s+(\S+)").unwrap();
     let re_offset = Regex::new(r"OFFSET (.+)").unwrap();
     let re_channels = Regex::new(r"CHANNELS (\d) (.+)").unwrap();
 
@@ -315,7 +393,8 @@ fn parse_bvh(lines: Lines) -> (BvhMetadata, BvhData) {
                 .unwrap()
                 .parse::<f64>()
                 .unwrap();
-            fps = (1.0 / frame_time) as u32;
+            // round instead of truncating (e.g. 0.0083334 would give 119 fps)
+            fps = (1.0 / frame_time).round() as u32;
             break; // jump to parsing Motion
         }
     }
@@ -0,0 +1,52 @@
+use crate::parse::__build_bvh_data_with_rest;
+use crate::types::*;
+use crate::utils::__fit_rotation;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Reconstruct an animation of a skeleton from global joint positions only (e.g. 3D keypoints from pose estimation).
+///
+/// `positions` are laid out like `pose_global_positions` (`[joint][frame]`), `rest_local_positions` are the joint offsets of the skeleton.
+/// Every joint is rotated so that its bones point at its children's positions. A joint with several children is fitted to all of them
+/// (e.g. hips to the spine and both legs), which also fixes its twist. A joint with one child has no information about its twist,
+/// so it's kept minimal (the joint twists with its parent). Leaf joints keep the rotation of their parent.
+/// Bones collapsed to zero length in `positions` (coincident joints) are ignored; a joint without any usable bone keeps the rotation of its parent.
+/// Bone lengths come from `rest_local_positions`, so the reconstructed positions only match the input up to the bone lengths.
+pub fn reconstruct_from_positions(
+    metadata: &BvhMetadata,
+    rest_local_positions: &[Position],
+    positions: &[Vec<Position>],
+) -> (BvhMetadata, BvhData) {
+    assert_eq!(positions.len(), metadata.joints.len(), "Positions have to be given for every joint!");
+    let num_frames = positions[0].len();
+    let num_joints = metadata.joints.len();
+
+    let mut local_rotations = vec![Vec::with_capacity(num_frames); num_joints];
+    let mut global_rotations = vec![Quaternion::identity(); num_joints];
+    for frame in 0..num_frames {
+        let frame_positions: Vec<Position> = positions.iter().map(|track| track[frame]).collect();
+        for joint in metadata.joints.iter() {
+            let parent_rotation = if joint.parent_index == -1 {
+                Quaternion::identity()
+            } else {
+                global_rotations[joint.parent_index as Index]
+            };
+            let from: Vec<Position> = joint.children.iter().map(|&child| rest_local_positions[child]).collect();
+            let to: Vec<Position> = joint
+                .children
+                .iter()
+                .map(|&child| frame_positions[child] - frame_positions[joint.index])
+                .collect();
+            let rotation = __fit_rotation(&from, &to, parent_rotation).unwrap_or(parent_rotation);
+            global_rotations[joint.index] = rotation;
+            local_rotations[joint.index].push(parent_rotation.conjugate() * rotation);
+        }
+    }
+
+    let new_metadata = BvhMetadata {
+        num_frames,
+        ..metadata.clone()
+    };
+    let new_data = __build_bvh_data_with_rest(&new_metadata, rest_local_positions.to_vec(), positions[0].clone(), local_rotations);
+    (new_metadata, new_data)
+}
@@ -0,0 +1,65 @@
+use crate::parse::__build_bvh_data;
+use crate::sample::{Interpolation, WrapMode};
+use crate::types::*;
+use crate::utils;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Resample the animation to a new frame rate. Returns new metadata (with updated `num_frames`, `frame_time` and `fps`) and data.
+    ///
+    /// When upsampling, root translation is lerped and rotations are slerped.
+    /// When downsampling, the animation is first low-pass filtered (Gaussian, rotations filtered in tangent space) to avoid aliasing.
+    pub fn resample(&self, metadata: &BvhMetadata, target_fps: f64) -> (BvhMetadata, BvhData) {
+        assert!(target_fps > 0.0, "Target fps must be positive!");
+        let source_fps = 1.0 / metadata.frame_time;
+        let ratio = source_fps / target_fps;
+
+        //// low-pass filter before decimating
+        let filtered;
+        let source = if ratio > 1.0 {
+            // Gaussian attenuating frequencies at the target Nyquist frequency by half
+            let sigma = ratio * (2.0 * 2f64.ln()).sqrt() / std::f64::consts::PI;
+            let kernel = utils::__gaussian_kernel(sigma);
+            let root_positions = utils::__convolve_positions(&self.pose_global_positions[0], &kernel);
+            let local_rotations = self
+                .pose_local_rotations
+                .iter()
+                .map(|track| utils::__convolve_rotations(track, &kernel))
+                .collect();
+            filtered = __build_bvh_data(metadata, self, root_positions, local_rotations);
+            &filtered
+        } else {
+            self
+        };
+
+        //// sample at the new frame times
+        let frame_time = 1.0 / target_fps;
+        let num_frames = if metadata.num_frames == 0 {
+            0
+        } else {
+            // small epsilon so that e.g. 1.0 / 0.0083333 * 0.0083333 doesn't lose the last frame
+            (metadata.duration() / frame_time + 1e-6).floor() as usize + 1
+        };
+
+        let num_joints = metadata.joints.len();
+        let mut root_positions = Vec::with_capacity(num_frames);
+        let mut local_rotations = vec![Vec::with_capacity(num_frames); num_joints];
+        for frame in 0..num_frames {
+            let pose = source.sample(metadata, frame as f64 * frame_time, WrapMode::Clamp, Interpolation::Linear);
+            root_positions.push(pose.root_position);
+            for (track, rotation) in local_rotations.iter_mut().zip(pose.local_rotations) {
+                track.push(rotation);
+            }
+        }
+
+        let new_metadata = BvhMetadata {
+            joints: metadata.joints.clone(),
+            num_frames,
+            frame_time,
+            fps: target_fps.round() as u32,
+        };
+        let new_data = __build_bvh_data(&new_metadata, self, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+}
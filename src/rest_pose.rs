@@ -0,0 +1,124 @@
+use crate::humanoid::HumanoidBone;
+use crate::parse::__build_bvh_data_with_rest;
+use crate::types::*;
+use crate::utils::__rotation_between;
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Direction of a joint's bone in its local frame: offset of the only child, average of the children's offsets or the endsite offset.
+fn __bone_direction(metadata: &BvhMetadata, data: &BvhData, index: Index) -> Position {
+    let joint = &metadata.joints[index];
+    if !joint.children.is_empty() {
+        let sum: Position = joint.children.iter().map(|&child| data.rest_local_positions[child]).sum();
+        return sum / joint.children.len() as f64;
+    }
+    joint.endsite.as_ref().map_or(Position::identity(), |endsite| endsite.offset)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Make the pose at `frame` the new rest pose (see `rebase_rest_pose_to`).
+    pub fn rebase_rest_pose(&self, metadata: &BvhMetadata, frame: usize) -> (BvhMetadata, BvhData) {
+        let pose = self.pose_at_frame(frame);
+        self.rebase_rest_pose_to(metadata, &pose)
+    }
+
+    /// Bake `pose` into the rest pose: joint offsets and endsites are rotated into the pose
+    /// (its root translation is ignored, root rotation is kept), and the local rotations of every frame are compensated,
+    /// so world-space positions and bone directions of the animation stay unchanged.
+    pub fn rebase_rest_pose_to(&self, metadata: &BvhMetadata, pose: &Pose) -> (BvhMetadata, BvhData) {
+        // Q = global rotations of the pose
+        // new offset: o' = Q_parent * o
+        // new rotations: G' = G * Q^-1  =>  L' = Q_parent * L * Q^-1
+        let q = &pose.global_rotations;
+        let mut new_metadata = metadata.clone();
+        let mut rest_local_positions = self.rest_local_positions.clone();
+        for joint in new_metadata.joints.iter_mut() {
+            if joint.parent_index != -1 {
+                rest_local_positions[joint.index] = q[joint.parent_index as Index] * self.rest_local_positions[joint.index];
+            }
+            if let Some(endsite) = joint.endsite.as_mut() {
+                endsite.offset = q[joint.index] * endsite.offset;
+            }
+        }
+
+        let local_rotations = metadata
+            .joints
+            .iter()
+            .map(|joint| {
+                let parent = if joint.parent_index == -1 {
+                    Quaternion::identity()
+                } else {
+                    q[joint.parent_index as Index]
+                };
+                let q_inv = q[joint.index].conjugate();
+                self.pose_local_rotations[joint.index]
+                    .iter()
+                    .map(|&l| parent * l * q_inv)
+                    .collect()
+            })
+            .collect();
+
+        let new_data = __build_bvh_data_with_rest(
+            &new_metadata,
+            rest_local_positions,
+            self.pose_global_positions[0].clone(),
+            local_rotations,
+        );
+        (new_metadata, new_data)
+    }
+
+    /// Change the rest pose of the arms (detected by `humanoid_mapping`) to a T-pose.
+    pub fn rest_to_t_pose(&self, metadata: &BvhMetadata) -> (BvhMetadata, BvhData) {
+        self.rest_to_a_pose(metadata, 0.0)
+    }
+
+    /// Change the rest pose of the arms (detected by `humanoid_mapping`) to an A-pose: arms straight, pointing sideways
+    /// and `angle` DEGREES down from horizontal (0 is a T-pose, usually 30-45). The animation itself stays unchanged.
+    ///
+    /// World up is +Y, the sideways direction is taken from the line between the upper arms in the current rest pose.
+    pub fn rest_to_a_pose(&self, metadata: &BvhMetadata, angle: f64) -> (BvhMetadata, BvhData) {
+        let mapping = metadata.humanoid_mapping();
+        let arm = |bones: [HumanoidBone; 3]| -> Vec<Index> {
+            bones
+                .iter()
+                .map(|&bone| mapping.get(bone).unwrap_or_else(|| panic!("Humanoid bone {:?} not found!", bone)))
+                .collect()
+        };
+        let left = arm([HumanoidBone::LeftUpperArm, HumanoidBone::LeftLowerArm, HumanoidBone::LeftHand]);
+        let right = arm([HumanoidBone::RightUpperArm, HumanoidBone::RightLowerArm, HumanoidBone::RightHand]);
+
+        //// sideways (towards left) and up directions
+        let up = Position::unit_y();
+        let mut sideways = self.rest_global_positions[left[0]] - self.rest_global_positions[right[0]];
+        sideways -= up * sideways.dot(up);
+        assert!(sideways.magnitude2() > 1e-12, "Can't find the sideways direction from the rest pose of the arms!");
+        let sideways = sideways.normalize();
+        let (sin, cos) = angle.to_radians().sin_cos();
+
+        //// aim the arm bones (parents first, so children are aimed in their updated frames)
+        let mut pose = Pose::new(
+            metadata,
+            self,
+            self.rest_local_positions[0],
+            vec![Quaternion::identity(); metadata.joints.len()],
+        );
+        for (chain, side) in [(left, 1.0), (right, -1.0)] {
+            let target = sideways * (side * cos) - up * sin;
+            for index in chain {
+                let current = pose.global_rotations[index] * __bone_direction(metadata, self, index);
+                let global = __rotation_between(current, target) * pose.global_rotations[index];
+                let parent = metadata.joints[index].parent_index;
+                pose.local_rotations[index] = if parent == -1 {
+                    global
+                } else {
+                    pose.global_rotations[parent as Index].conjugate() * global
+                };
+                pose = Pose::new(metadata, self, pose.root_position, pose.local_rotations);
+            }
+        }
+        self.rebase_rest_pose_to(metadata, &pose)
+    }
+}
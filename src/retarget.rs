@@ -0,0 +1,152 @@
+use crate::mirror::NamePairing;
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use cgmath::InnerSpace;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Mapping of source skeleton joints to target skeleton joints (by name).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JointMap {
+    /// (source joint name, target joint name)
+    pub pairs: Vec<(String, String)>,
+}
+
+impl JointMap {
+    pub fn from_pairs(pairs: &[(&str, &str)]) -> JointMap {
+        JointMap {
+            pairs: pairs.iter().map(|(s, t)| (s.to_string(), t.to_string())).collect(),
+        }
+    }
+
+    /// Map every joint of `source` to the joint with the same name in `target` (if there is one).
+    pub fn by_same_names(source: &BvhMetadata, target: &BvhMetadata) -> JointMap {
+        JointMap {
+            pairs: source
+                .joints
+                .iter()
+                .filter(|joint| target.joints.iter().any(|j| j.name == joint.name))
+                .map(|joint| (joint.name.clone(), joint.name.clone()))
+                .collect(),
+        }
+    }
+
+    /// Source joint index for every target joint (`None` for unmapped target joints).
+    fn __resolve(&self, source: &BvhMetadata, target: &BvhMetadata) -> Vec<Option<Index>> {
+        let mut mapping = vec![None; target.joints.len()];
+        for (source_name, target_name) in self.pairs.iter() {
+            mapping[target.find_joint_by_name(target_name).index] = Some(source.find_joint_by_name(source_name).index);
+        }
+        mapping
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Leg length of a skeleton in its rest pose: the longest bone chain from the root down one of its left/right children (e.g. UpLeg -> Leg -> Foot -> Toe).
+/// Falls back to the longest chain from the root if the root has no left/right children.
+pub fn leg_length(metadata: &BvhMetadata, data: &BvhData) -> f64 {
+    fn ____chain_length(index: Index, metadata: &BvhMetadata, data: &BvhData) -> f64 {
+        let joint = &metadata.joints[index];
+        let endsite = joint.endsite.as_ref().map_or(0.0, |endsite| endsite.offset.magnitude());
+        joint
+            .children
+            .iter()
+            .map(|&child| data.rest_local_positions[child].magnitude() + ____chain_length(child, metadata, data))
+            .fold(endsite, f64::max)
+    }
+
+    let pairing = NamePairing::default();
+    let root = &metadata.joints[0];
+    let chain_from = |child: &Index| data.rest_local_positions[*child].magnitude() + ____chain_length(*child, metadata, data);
+    let legs = root
+        .children
+        .iter()
+        .filter(|&&child| pairing.side(&metadata.joints[child].name).is_some())
+        .map(chain_from)
+        .fold(0.0, f64::max);
+    if legs > 0.0 {
+        legs
+    } else {
+        root.children.iter().map(chain_from).fold(0.0, f64::max)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Retarget this animation onto a different skeleton (`target_metadata`, rest pose taken from `target_data`).
+    ///
+    /// Mapped target joints copy the world-space orientation of their source bones, accounting for the differing rest orientations
+    /// (`rest_global_rotations`) of the two skeletons. Unmapped target joints keep their rest pose relative to the parent.
+    /// Target bone lengths are kept, root translation is scaled by the leg length ratio (or by `root_scale` if given).
+    /// The target root joint has to be mapped.
+    pub fn retarget(
+        &self,
+        metadata: &BvhMetadata,
+        target_metadata: &BvhMetadata,
+        target_data: &BvhData,
+        joint_map: &JointMap,
+        root_scale: Option<f64>,
+    ) -> (BvhMetadata, BvhData) {
+        let mapping = joint_map.__resolve(metadata, target_metadata);
+        let source_root = mapping[0].expect("Target root joint has to be mapped to a source joint!");
+        let num_frames = metadata.num_frames;
+
+        //// global rotations: target bone frame (G_t * rest_t) = source bone frame (G_s * rest_s)
+        let mut global_rotations: Vec<Vec<Quaternion>> = vec![Vec::new(); target_metadata.joints.len()];
+        for joint in target_metadata.joints.iter() {
+            global_rotations[joint.index] = match mapping[joint.index] {
+                Some(source) => {
+                    let rest_correction = self.rest_global_rotations[source] * target_data.rest_global_rotations[joint.index].conjugate();
+                    self.pose_global_rotations[source]
+                        .iter()
+                        .map(|&q| q * rest_correction)
+                        .collect()
+                }
+                // unmapped joint follows its parent
+                None => global_rotations[joint.parent_index as Index].clone(),
+            };
+        }
+
+        //// back to local rotations
+        let local_rotations = target_metadata
+            .joints
+            .iter()
+            .map(|joint| {
+                if joint.parent_index == -1 {
+                    return global_rotations[joint.index].clone();
+                }
+                global_rotations[joint.parent_index as Index]
+                    .iter()
+                    .zip(global_rotations[joint.index].iter())
+                    .map(|(parent, child)| parent.conjugate() * child)
+                    .collect()
+            })
+            .collect();
+
+        //// root translation
+        let scale = root_scale.unwrap_or_else(|| {
+            let source_leg = leg_length(metadata, self);
+            if source_leg > 0.0 {
+                leg_length(target_metadata, target_data) / source_leg
+            } else {
+                1.0
+            }
+        });
+        let root_positions = self.pose_global_positions[source_root]
+            .iter()
+            .take(num_frames)
+            .map(|&p| p * scale)
+            .collect();
+
+        let new_metadata = BvhMetadata {
+            joints: target_metadata.joints.clone(),
+            num_frames,
+            frame_time: metadata.frame_time,
+            fps: metadata.fps,
+        };
+        let new_data = __build_bvh_data(&new_metadata, target_data, root_positions, local_rotations);
+        (new_metadata, new_data)
+    }
+}
@@ -0,0 +1,113 @@
+use crate::mirror::{NamePairing, Side};
+use crate::parse::__build_bvh_data;
+use crate::types::*;
+use cgmath::{InnerSpace, Rad, Rotation3};
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Ground-projected trajectory of the root (one entry per frame).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootTrajectory {
+    /// Root position projected on the ground plane (y = 0).
+    pub positions: Vec<Position>,
+    /// Rotation around the Y axis in radians (0 = facing +Z). Unwrapped, so it doesn't jump between -PI and PI.
+    pub headings: Vec<f64>,
+}
+
+impl RootTrajectory {
+    pub fn num_frames(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Heading of a frame as a quaternion.
+    pub fn rotation(&self, frame: usize) -> Quaternion {
+        Quaternion::from_angle_y(Rad(self.headings[frame]))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Guess which direction the character faces, expressed in the root joint's local frame.
+    ///
+    /// Uses the up direction (towards the root children in the middle, e.g. spine) and the right direction (from the left to the right root children, e.g. legs).
+    /// Falls back to the direction that is +Z in the first frame if the root has no left/right children.
+    pub fn root_forward_axis(&self, metadata: &BvhMetadata) -> Position {
+        let root = &metadata.joints[0];
+        let pairing = NamePairing::default();
+        let mut right = Position::identity();
+        let mut up = Position::identity();
+        for &child in root.children.iter() {
+            let offset = self.rest_local_positions[child];
+            match pairing.side(&metadata.joints[child].name) {
+                Some(Side::Right) => right += offset,
+                Some(Side::Left) => right -= offset,
+                None => up += offset,
+            }
+        }
+
+        if right.magnitude2() > 1e-12 && up.magnitude2() > 1e-12 {
+            let forward = up.cross(right);
+            if forward.magnitude2() > 1e-12 {
+                return forward.normalize();
+            }
+        }
+        if metadata.num_frames == 0 {
+            return Position::unit_z();
+        }
+        self.pose_global_rotations[0][0].conjugate() * Position::unit_z()
+    }
+
+    /// Extract the ground-projected root trajectory (position + heading) from the root track.
+    /// `forward` is the facing direction in the root joint's local frame (see `root_forward_axis`).
+    pub fn extract_root_trajectory(&self, metadata: &BvhMetadata, forward: Position) -> RootTrajectory {
+        let mut headings: Vec<f64> = Vec::with_capacity(metadata.num_frames);
+        for frame in 0..metadata.num_frames {
+            let f = self.pose_global_rotations[0][frame] * forward;
+            let mut heading = f.x.atan2(f.z);
+            //// unwrap
+            if let Some(&previous) = headings.last() {
+                heading += ((previous - heading) / std::f64::consts::TAU).round() * std::f64::consts::TAU;
+            }
+            headings.push(heading);
+        }
+        let positions = self.pose_global_positions[0]
+            .iter()
+            .take(metadata.num_frames)
+            .map(|p| Position::new(p.x, 0.0, p.z))
+            .collect();
+        RootTrajectory { positions, headings }
+    }
+
+    /// Make an in-place version of the animation: the root trajectory is removed, so the character stays above the origin facing +Z.
+    /// Returns the removed trajectory and the in-place animation. `apply_root_trajectory` reverses it.
+    pub fn to_in_place(&self, metadata: &BvhMetadata, forward: Position) -> (RootTrajectory, BvhData) {
+        let trajectory = self.extract_root_trajectory(metadata, forward);
+        let mut root_positions = Vec::with_capacity(metadata.num_frames);
+        let mut local_rotations = self.pose_local_rotations.clone();
+        for (frame, root_rotation) in local_rotations[0].iter_mut().enumerate() {
+            let heading_inv = trajectory.rotation(frame).conjugate();
+            root_positions.push(heading_inv * (self.pose_global_positions[0][frame] - trajectory.positions[frame]));
+            *root_rotation = heading_inv * *root_rotation;
+        }
+        let data = __build_bvh_data(metadata, self, root_positions, local_rotations);
+        (trajectory, data)
+    }
+
+    /// Put a root trajectory onto an (in-place) animation: root position and rotation are transformed by the trajectory of every frame.
+    pub fn apply_root_trajectory(&self, metadata: &BvhMetadata, trajectory: &RootTrajectory) -> BvhData {
+        assert_eq!(
+            trajectory.num_frames(),
+            metadata.num_frames,
+            "Trajectory has different number of frames than the animation!"
+        );
+        let mut root_positions = Vec::with_capacity(metadata.num_frames);
+        let mut local_rotations = self.pose_local_rotations.clone();
+        for (frame, root_rotation) in local_rotations[0].iter_mut().enumerate() {
+            let heading = trajectory.rotation(frame);
+            root_positions.push(trajectory.positions[frame] + heading * self.pose_global_positions[0][frame]);
+            *root_rotation = heading * *root_rotation;
+        }
+        __build_bvh_data(metadata, self, root_positions, local_rotations)
+    }
+}
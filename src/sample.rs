@@ -0,0 +1,168 @@
+use crate::types::*;
+use crate::utils;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// What happens when sampling outside of the animation's time range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Hold the first/last frame.
+    Clamp,
+    /// Play the animation again from the start (the last frame blends into the first one).
+    Loop,
+    /// Play the animation forwards, then backwards, then forwards...
+    PingPong,
+}
+
+/// How to interpolate between neighbouring frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Linear interpolation for root translation and slerp for rotations.
+    Linear,
+    /// Catmull-Rom spline for root translation and squad for rotations.
+    Cubic,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Map an (out of range) frame index into `0..num_frames` according to the wrap mode.
+fn __wrap_frame_index(frame: isize, num_frames: usize, wrap: WrapMode) -> usize {
+    let last = num_frames as isize - 1;
+    if last <= 0 {
+        return 0;
+    }
+    match wrap {
+        WrapMode::Clamp => frame.clamp(0, last) as usize,
+        WrapMode::Loop => frame.rem_euclid(num_frames as isize) as usize,
+        WrapMode::PingPong => {
+            let f = frame.rem_euclid(2 * last);
+            (if f > last { 2 * last - f } else { f }) as usize
+        }
+    }
+}
+
+/// Convert time (in seconds) to a (fractional) frame within `0..num_frames`.
+fn __wrap_frame(time: f64, metadata: &BvhMetadata, wrap: WrapMode) -> f64 {
+    let last = metadata.num_frames as f64 - 1.0;
+    if last <= 0.0 {
+        return 0.0;
+    }
+    let frame = time / metadata.frame_time;
+    match wrap {
+        WrapMode::Clamp => frame.clamp(0.0, last),
+        WrapMode::Loop => frame.rem_euclid(metadata.num_frames as f64),
+        WrapMode::PingPong => {
+            let f = frame.rem_euclid(2.0 * last);
+            if f > last {
+                2.0 * last - f
+            } else {
+                f
+            }
+        }
+    }
+}
+
+/// Catmull-Rom spline between `p1` and `p2`.
+fn __catmull_rom(p0: Position, p1: Position, p2: Position, p3: Position, t: f64) -> Position {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (-p0 + p1 * 3.0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+/// Squad control point of `q1` (with neighbours `q0` and `q2`).
+fn __squad_control_point(q0: Quaternion, q1: Quaternion, q2: Quaternion) -> Quaternion {
+    let q1_inv = q1.conjugate();
+    let log_next = utils::__quat_log(q1_inv * q2);
+    let log_prev = utils::__quat_log(q1_inv * q0);
+    q1 * utils::__quat_exp(-(log_next + log_prev) / 4.0)
+}
+
+/// Spherical quadrangle interpolation between `q1` and `q2`.
+fn __squad(q0: Quaternion, q1: Quaternion, q2: Quaternion, q3: Quaternion, t: f64) -> Quaternion {
+    // keep all 4 quaternions in the same hemisphere, otherwise control points go haywire
+    let q0 = utils::__same_hemisphere(q0, q1);
+    let q2 = utils::__same_hemisphere(q2, q1);
+    let q3 = utils::__same_hemisphere(q3, q2);
+    let s1 = __squad_control_point(q0, q1, q2);
+    let s2 = __squad_control_point(q1, q2, q3);
+    q1.slerp(q2, t).slerp(s1.slerp(s2, t), 2.0 * t * (1.0 - t))
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhMetadata {
+    /// Time (in seconds) between the first and the last frame.
+    pub fn duration(&self) -> f64 {
+        self.num_frames.saturating_sub(1) as f64 * self.frame_time
+    }
+}
+
+impl BvhData {
+    /// The rest pose of the skeleton (identity rotations, so it can be passed to `Pose::new` like any other pose).
+    pub fn rest_pose(&self) -> Pose {
+        let num_joints = self.rest_global_positions.len();
+        Pose {
+            root_position: self.rest_global_positions[0],
+            local_rotations: vec![Quaternion::identity(); num_joints],
+            global_positions: self.rest_global_positions.clone(),
+            global_rotations: vec![Quaternion::identity(); num_joints],
+        }
+    }
+
+    /// Get the pose at an integer frame. Frames past the end give the last frame, an animation without frames gives the rest pose.
+    pub fn pose_at_frame(&self, frame: usize) -> Pose {
+        let num_frames = self.pose_local_rotations[0].len();
+        if num_frames == 0 {
+            return self.rest_pose();
+        }
+        let frame = frame.min(num_frames - 1);
+        Pose {
+            root_position: self.pose_global_positions[0][frame],
+            local_rotations: self.pose_local_rotations.iter().map(|track| track[frame]).collect(),
+            global_positions: self.pose_global_positions.iter().map(|track| track[frame]).collect(),
+            global_rotations: self.pose_global_rotations.iter().map(|track| track[frame]).collect(),
+        }
+    }
+
+    /// Sample the animation at an arbitrary time (in seconds), interpolating between the surrounding frames.
+    ///
+    /// Root translation and local rotations are interpolated, global positions and rotations are then calculated with forward kinematics.
+    /// An animation without frames gives the rest pose.
+    pub fn sample(
+        &self,
+        metadata: &BvhMetadata,
+        time: f64,
+        wrap: WrapMode,
+        interpolation: Interpolation,
+    ) -> Pose {
+        if metadata.num_frames == 0 {
+            return self.rest_pose();
+        }
+        let frame = __wrap_frame(time, metadata, wrap);
+        let i1 = frame.floor() as isize;
+        let t = frame - i1 as f64;
+
+        let n = metadata.num_frames;
+        let f0 = __wrap_frame_index(i1 - 1, n, wrap);
+        let f1 = __wrap_frame_index(i1, n, wrap);
+        let f2 = __wrap_frame_index(i1 + 1, n, wrap);
+        let f3 = __wrap_frame_index(i1 + 2, n, wrap);
+
+        let root = &self.pose_global_positions[0];
+        let root_position = match interpolation {
+            Interpolation::Linear => root[f1] + (root[f2] - root[f1]) * t,
+            Interpolation::Cubic => __catmull_rom(root[f0], root[f1], root[f2], root[f3], t),
+        };
+
+        let local_rotations = self
+            .pose_local_rotations
+            .iter()
+            .map(|track| match interpolation {
+                Interpolation::Linear => track[f1].slerp(track[f2], t),
+                Interpolation::Cubic => __squad(track[f0], track[f1], track[f2], track[f3], t),
+            })
+            .collect();
+
+        Pose::new(metadata, self, root_position, local_rotations)
+    }
+}
@@ -0,0 +1,363 @@
+use crate::parse::__build_bvh_data_with_rest;
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Rebuild a coherent hierarchy from joints of which only `name`, `parent_index` (index into `joints`) and `endsite` are trusted.
+/// Joints are reordered depth-first (parents before children, siblings keep their order); `index`, `depth`, `children` and `is_leaf` are recalculated.
+/// Returns the new joints and, for every new joint, its index in `joints`.
+pub(crate) fn __rebuild_hierarchy(joints: &[Joint]) -> (Vec<Joint>, Vec<Index>) {
+    let roots: Vec<Index> = (0..joints.len()).filter(|&i| joints[i].parent_index == -1).collect();
+    assert_eq!(roots.len(), 1, "Skeleton has to have exactly one root joint!");
+    let mut children: Vec<Vec<Index>> = vec![Vec::new(); joints.len()];
+    for (i, joint) in joints.iter().enumerate() {
+        if joint.parent_index != -1 {
+            children[joint.parent_index as Index].push(i);
+        }
+    }
+
+    //// depth-first order
+    let mut order: Vec<Index> = Vec::with_capacity(joints.len());
+    let mut depths: Vec<Depth> = Vec::with_capacity(joints.len());
+    let mut stack = vec![(roots[0], 0)];
+    while let Some((i, depth)) = stack.pop() {
+        order.push(i);
+        depths.push(depth);
+        stack.extend(children[i].iter().rev().map(|&child| (child, depth + 1)));
+    }
+    assert_eq!(order.len(), joints.len(), "Skeleton hierarchy contains a cycle!");
+
+    let mut new_index = vec![0; joints.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_index[old] = new;
+    }
+    let new_joints = order
+        .iter()
+        .zip(depths)
+        .map(|(&old, depth)| {
+            let joint = &joints[old];
+            Joint {
+                name: joint.name.clone(),
+                index: new_index[old],
+                parent_index: if joint.parent_index == -1 { -1 } else { new_index[joint.parent_index as Index] as ParentIndex },
+                depth,
+                children: children[old].iter().map(|&child| new_index[child]).collect(),
+                is_leaf: joint.endsite.is_some(),
+                endsite: joint.endsite.clone(),
+            }
+        })
+        .collect();
+    (new_joints, order)
+}
+
+/// Build the edited skeleton: `joints` as for `__rebuild_hierarchy`, with their rest offsets, root positions and per-frame global rotations.
+/// Local rotations are derived from the global rotations, so the world-space orientation of every joint is preserved.
+/// Returns the new metadata, data and the index of every new joint in `joints`.
+pub(crate) fn __rebuild_skeleton(
+    metadata: &BvhMetadata,
+    joints: &[Joint],
+    rest_local_positions: &[Position],
+    root_positions: Vec<Position>,
+    global_rotations: &[Vec<Quaternion>],
+) -> (BvhMetadata, BvhData, Vec<Index>) {
+    let (new_joints, order) = __rebuild_hierarchy(joints);
+    let local_rotations = new_joints
+        .iter()
+        .map(|joint| {
+            let old = order[joint.index];
+            if joint.parent_index == -1 {
+                return global_rotations[old].clone();
+            }
+            global_rotations[order[joint.parent_index as Index]]
+                .iter()
+                .zip(global_rotations[old].iter())
+                .map(|(parent, child)| parent.conjugate() * child)
+                .collect()
+        })
+        .collect();
+    let rest_local_positions = order.iter().map(|&old| rest_local_positions[old]).collect();
+
+    let new_metadata = BvhMetadata {
+        joints: new_joints,
+        ..metadata.clone()
+    };
+    let new_data = __build_bvh_data_with_rest(&new_metadata, rest_local_positions, root_positions, local_rotations);
+    (new_metadata, new_data, order)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Remove joints from the skeleton (e.g. twist, finger or zero-length joints).
+    ///
+    /// Children of a removed joint are attached to its closest kept ancestor, and their local rotations absorb the removed joints'
+    /// rotations, so global rotations of all kept joints stay identical. Their new offset (constant, as offsets of non-root joints are)
+    /// is their average position relative to that ancestor over the animation, like in `reparent_joint`.
+    /// Global positions are therefore kept exactly when the removed joints don't move the children relative to the ancestor
+    /// (zero-length joints, twist joints rotating around the bone, joints with a constant rotation) and approximately otherwise.
+    /// A kept joint that loses all of its children gets an endsite in place of its first removed child.
+    ///
+    /// Returns the new metadata, data and the new index of every old joint (`None` for removed joints). The root joint can't be removed.
+    pub fn remove_joints(&self, metadata: &BvhMetadata, indices: &[Index]) -> (BvhMetadata, BvhData, Vec<Option<Index>>) {
+        let num_joints = metadata.joints.len();
+        let mut removed = vec![false; num_joints];
+        for &index in indices {
+            removed[index] = true;
+        }
+        assert!(!removed[0], "Root joint can't be removed!");
+
+        let kept: Vec<Index> = (0..num_joints).filter(|&i| !removed[i]).collect();
+        let mut compact = vec![None; num_joints];
+        for (k, &old) in kept.iter().enumerate() {
+            compact[old] = Some(k);
+        }
+
+        //// kept joints attached to the closest kept ancestor
+        let mut joints = Vec::with_capacity(kept.len());
+        let mut rest_local_positions = Vec::with_capacity(kept.len());
+        for &old in kept.iter() {
+            let mut joint = metadata.joints[old].clone();
+            let mut ancestor = joint.parent_index;
+            while ancestor != -1 && removed[ancestor as Index] {
+                ancestor = metadata.joints[ancestor as Index].parent_index;
+            }
+            joint.parent_index = if ancestor == -1 { -1 } else { compact[ancestor as Index].unwrap() as ParentIndex };
+            rest_local_positions.push(if ancestor == -1 || ancestor == metadata.joints[old].parent_index {
+                self.rest_local_positions[old]
+            } else if metadata.num_frames > 0 {
+                let ancestor = ancestor as Index;
+                let sum: Position = (0..metadata.num_frames)
+                    .map(|frame| {
+                        self.pose_global_rotations[ancestor][frame].conjugate()
+                            * (self.pose_global_positions[old][frame] - self.pose_global_positions[ancestor][frame])
+                    })
+                    .sum();
+                sum / metadata.num_frames as f64
+            } else {
+                self.rest_global_positions[old] - self.rest_global_positions[ancestor as Index]
+            });
+
+            //// keep the bone direction of joints which lose all of their children
+            let first_child = joint.children.first().copied();
+            if joint.endsite.is_none() && joint.children.iter().all(|&child| removed[child]) {
+                if let Some(child) = first_child {
+                    joint.endsite = Some(Endsite {
+                        offset: self.rest_local_positions[child],
+                    });
+                }
+            }
+            joints.push(joint);
+        }
+
+        let global_rotations: Vec<Vec<Quaternion>> = kept.iter().map(|&old| self.pose_global_rotations[old].clone()).collect();
+        let (new_metadata, new_data, order) = __rebuild_skeleton(
+            metadata,
+            &joints,
+            &rest_local_positions,
+            self.pose_global_positions[0].clone(),
+            &global_rotations,
+        );
+
+        let mut remap = vec![None; num_joints];
+        for (new, &k) in order.iter().enumerate() {
+            remap[kept[k]] = Some(new);
+        }
+        (new_metadata, new_data, remap)
+    }
+
+    /// Keep only the joints for which `predicate` returns true (see `remove_joints`). The root joint has to be kept.
+    pub fn keep_joints<F: Fn(&Joint) -> bool>(&self, metadata: &BvhMetadata, predicate: F) -> (BvhMetadata, BvhData, Vec<Option<Index>>) {
+        let removed: Vec<Index> = metadata
+            .joints
+            .iter()
+            .filter(|joint| !predicate(joint))
+            .map(|joint| joint.index)
+            .collect();
+        self.remove_joints(metadata, &removed)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Why joints can't be renamed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenameError {
+    /// The pattern isn't a valid regex.
+    InvalidPattern(String),
+    /// The joint would get the same name as another joint.
+    DuplicateName { joint: String, name: String },
+}
+
+impl BvhMetadata {
+    /// Rename a joint. Fails (keeping the old name) if another joint already has the name.
+    pub fn rename_joint(&mut self, index: Index, name: &str) -> Result<(), RenameError> {
+        if let Some(other) = self.joints.iter().find(|joint| joint.index != index && joint.name == name) {
+            return Err(RenameError::DuplicateName {
+                joint: self.joints[index].name.clone(),
+                name: other.name.clone(),
+            });
+        }
+        self.joints[index].name = name.to_string();
+        Ok(())
+    }
+
+    /// Rename all joints matching the regex `pattern` (e.g. `"^mixamorig:"` -> `""`). `replacement` can use capture groups (`$1`).
+    /// All new names are checked first: if any two joints would end up with the same name, nothing is renamed.
+    /// Returns the number of renamed joints.
+    pub fn rename_joints(&mut self, pattern: &str, replacement: &str) -> Result<usize, RenameError> {
+        let re = regex::Regex::new(pattern).map_err(|error| RenameError::InvalidPattern(error.to_string()))?;
+        let names: Vec<String> = self.joints.iter().map(|joint| re.replace_all(&joint.name, replacement).to_string()).collect();
+        let mut seen = std::collections::HashSet::new();
+        for (joint, name) in self.joints.iter().zip(names.iter()) {
+            if !seen.insert(name.as_str()) {
+                return Err(RenameError::DuplicateName {
+                    joint: joint.name.clone(),
+                    name: name.clone(),
+                });
+            }
+        }
+
+        let mut renamed = 0;
+        for (joint, name) in self.joints.iter_mut().zip(names) {
+            if joint.name != name {
+                joint.name = name;
+                renamed += 1;
+            }
+        }
+        Ok(renamed)
+    }
+}
+
+impl BvhData {
+    /// Add a joint as the last child of `parent` (e.g. a weapon joint in the hand). It has no rotation of its own,
+    /// so it rigidly follows the parent. It gets an endsite continuing in the direction of `offset`.
+    ///
+    /// Returns the new metadata, data and the new index of every old joint (indices after the inserted joint shift by one).
+    pub fn add_joint(&self, metadata: &BvhMetadata, parent: Index, name: &str, offset: Position) -> (BvhMetadata, BvhData, Vec<Index>) {
+        assert!(
+            metadata.joints.iter().all(|joint| joint.name != name),
+            "Joint {} already exists!",
+            name
+        );
+        let num_joints = metadata.joints.len();
+        let mut joints = metadata.joints.clone();
+        // a joint has either children or an endsite
+        joints[parent].endsite = None;
+        joints.push(Joint {
+            name: name.to_string(),
+            index: num_joints,
+            parent_index: parent as ParentIndex,
+            depth: 0,
+            children: Vec::new(),
+            is_leaf: true,
+            endsite: Some(Endsite { offset }),
+        });
+
+        let mut rest_local_positions = self.rest_local_positions.clone();
+        rest_local_positions.push(offset);
+        let mut global_rotations = self.pose_global_rotations.clone();
+        global_rotations.push(self.pose_global_rotations[parent].clone());
+
+        let (new_metadata, new_data, order) = __rebuild_skeleton(
+            metadata,
+            &joints,
+            &rest_local_positions,
+            self.pose_global_positions[0].clone(),
+            &global_rotations,
+        );
+        (new_metadata, new_data, __old_to_new(&order, num_joints))
+    }
+
+    /// Insert a new root joint above the current root (e.g. a `Root` joint on the ground under Hips).
+    ///
+    /// The old root is attached to the new one with `offset` (e.g. `(0, hips height, 0)`). Only the root joint carries translation
+    /// in `BvhData`, so the new root follows the old root's translation (minus `offset`) and has no rotation;
+    /// world-space motion of all the old joints stays unchanged.
+    pub fn insert_root_joint(&self, metadata: &BvhMetadata, name: &str, offset: Position) -> (BvhMetadata, BvhData, Vec<Index>) {
+        assert!(
+            metadata.joints.iter().all(|joint| joint.name != name),
+            "Joint {} already exists!",
+            name
+        );
+        let num_joints = metadata.joints.len();
+        let mut joints = metadata.joints.clone();
+        joints[0].parent_index = num_joints as ParentIndex;
+        joints.push(Joint {
+            name: name.to_string(),
+            index: num_joints,
+            parent_index: -1,
+            depth: 0,
+            children: Vec::new(),
+            is_leaf: false,
+            endsite: None,
+        });
+
+        let mut rest_local_positions = self.rest_local_positions.clone();
+        rest_local_positions[0] = offset;
+        rest_local_positions.push(self.rest_local_positions[0] - offset);
+        let mut global_rotations = self.pose_global_rotations.clone();
+        global_rotations.push(vec![Quaternion::identity(); metadata.num_frames]);
+        let root_positions = self.pose_global_positions[0].iter().map(|&p| p - offset).collect();
+
+        let (new_metadata, new_data, order) =
+            __rebuild_skeleton(metadata, &joints, &rest_local_positions, root_positions, &global_rotations);
+        (new_metadata, new_data, __old_to_new(&order, num_joints))
+    }
+
+    /// Move the subtree of joint `index` under `new_parent`, keeping world-space rotations of all joints unchanged.
+    ///
+    /// The new offset (constant, as offsets of non-root joints are) is the joint's average position relative to the new parent
+    /// over the animation, so positions are kept exactly if the joint moves rigidly with the new parent (e.g. a weapon held in a hand).
+    /// Returns the new metadata, data and the new index of every old joint.
+    pub fn reparent_joint(&self, metadata: &BvhMetadata, index: Index, new_parent: Index) -> (BvhMetadata, BvhData, Vec<Index>) {
+        assert!(index != 0, "Root joint can't be reparented!");
+        assert!(
+            !metadata.get_subtree(index).contains(&new_parent),
+            "Joint can't be reparented under its own descendant!"
+        );
+        let num_joints = metadata.joints.len();
+        let old_parent = metadata.joints[index].parent_index as Index;
+        let mut joints = metadata.joints.clone();
+        joints[index].parent_index = new_parent as ParentIndex;
+        joints[new_parent].endsite = None;
+        //// keep the bone direction of the old parent if it loses its only child
+        if joints[old_parent].children.len() == 1 && joints[old_parent].endsite.is_none() {
+            joints[old_parent].endsite = Some(Endsite {
+                offset: self.rest_local_positions[index],
+            });
+        }
+
+        let mut rest_local_positions = self.rest_local_positions.clone();
+        rest_local_positions[index] = if metadata.num_frames > 0 {
+            let sum: Position = (0..metadata.num_frames)
+                .map(|frame| {
+                    self.pose_global_rotations[new_parent][frame].conjugate()
+                        * (self.pose_global_positions[index][frame] - self.pose_global_positions[new_parent][frame])
+                })
+                .sum();
+            sum / metadata.num_frames as f64
+        } else {
+            self.rest_global_positions[index] - self.rest_global_positions[new_parent]
+        };
+
+        let (new_metadata, new_data, order) = __rebuild_skeleton(
+            metadata,
+            &joints,
+            &rest_local_positions,
+            self.pose_global_positions[0].clone(),
+            &self.pose_global_rotations,
+        );
+        (new_metadata, new_data, __old_to_new(&order, num_joints))
+    }
+}
+
+/// Invert the order returned by `__rebuild_skeleton` for the first `num_joints` (old) joints.
+fn __old_to_new(order: &[Index], num_joints: usize) -> Vec<Index> {
+    let mut remap = vec![0; num_joints];
+    for (new, &old) in order.iter().enumerate() {
+        if old < num_joints {
+            remap[old] = new;
+        }
+    }
+    remap
+}
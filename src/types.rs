@@ -1,7 +1,7 @@
 use cgmath::One;
 
 /////////////////////////////////////////////////////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BvhData {
     /// This is the same as OFFSET in the HIERARCHY of .bvh file
     pub rest_local_positions: Vec<Position>, // for root joint it's the same thing as its rest_global_positions
@@ -17,6 +17,11 @@ pub struct BvhData {
 }
 
 impl BvhData {
+    /// Recalculate global pose positions and rotations (forward kinematics) after editing `pose_local_rotations` or the root translation (`pose_global_positions[0]`).
+    pub fn recalculate_global_pose(&mut self, metadata: &BvhMetadata) {
+        crate::parse::__calc_pose(metadata, self);
+    }
+
     pub fn print_rest_local(&self) {
         println!("==== REST LOCAL ====");
         for i in 0..self.rest_local_positions.len() {
@@ -43,6 +48,40 @@ impl BvhData {
 
 /////////////////////////////////////////////////////////////////////////////////////////////////
 
+/// A single pose of the skeleton (e.g. one frame of `BvhData` or a pose sampled in between frames).
+#[derive(Debug, Clone)]
+pub struct Pose {
+    pub root_position: Position,
+    pub local_rotations: Vec<Quaternion>, // every joint has it
+    pub global_positions: Vec<Position>,
+    pub global_rotations: Vec<Quaternion>,
+}
+
+impl Pose {
+    /// Create a pose from the root position and local rotations. Global positions and rotations are calculated with forward kinematics.
+    pub fn new(
+        metadata: &BvhMetadata,
+        data: &BvhData,
+        root_position: Position,
+        local_rotations: Vec<Quaternion>,
+    ) -> Pose {
+        let (global_positions, global_rotations) = crate::parse::__calc_global_pose(
+            metadata,
+            &data.rest_local_positions,
+            &local_rotations,
+            root_position,
+        );
+        Pose {
+            root_position,
+            local_rotations,
+            global_positions,
+            global_rotations,
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////////////////////
+
 pub type Index = usize;
 pub type ParentIndex = isize; // can be -1 if joint has no parent
 pub type Quaternion = cgmath::Quaternion<f64>;
@@ -51,7 +90,7 @@ pub type Depth = usize;
 
 /////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Joint {
     pub name: String,
     pub index: Index,
@@ -62,12 +101,12 @@ pub struct Joint {
     pub endsite: Option<Endsite>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Endsite {
     pub offset: Position,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BvhMetadata {
     pub joints: Vec<Joint>,
     pub num_frames: usize,
@@ -119,6 +158,17 @@ impl BvhMetadata {
         
         return kinematic_chains
     }
+
+    /// Returns indices of the joint and all of its descendants (depth-first, parents before children).
+    pub fn get_subtree(&self, index: Index) -> Vec<Index> {
+        let mut subtree = Vec::new();
+        let mut stack = vec![index];
+        while let Some(i) = stack.pop() {
+            subtree.push(i);
+            stack.extend(self.joints[i].children.iter().rev());
+        }
+        subtree
+    }
 }
 
 
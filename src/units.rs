@@ -0,0 +1,104 @@
+use crate::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Length unit a .bvh file can be authored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Millimeters,
+    Centimeters,
+    Meters,
+    Inches,
+    Feet,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 5] = [
+        Unit::Millimeters,
+        Unit::Centimeters,
+        Unit::Meters,
+        Unit::Inches,
+        Unit::Feet,
+    ];
+
+    /// How many meters one unit is.
+    pub fn meters_per_unit(self) -> f64 {
+        match self {
+            Unit::Millimeters => 0.001,
+            Unit::Centimeters => 0.01,
+            Unit::Meters => 1.0,
+            Unit::Inches => 0.0254,
+            Unit::Feet => 0.3048,
+        }
+    }
+}
+
+/// Height (in meters) of an average human skeleton measured from the lowest to the highest joint. Used by `guess_unit`.
+const TYPICAL_SKELETON_HEIGHT: f64 = 1.7;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl BvhData {
+    /// Uniformly scale the skeleton and the animation by `factor`.
+    ///
+    /// Scales rest offsets, End Site offsets (stored in `metadata`), root translations and all global positions. Rotations are left untouched.
+    pub fn rescale(&mut self, metadata: &mut BvhMetadata, factor: f64) {
+        for p in self.rest_local_positions.iter_mut() {
+            *p *= factor;
+        }
+        for p in self.rest_global_positions.iter_mut() {
+            *p *= factor;
+        }
+        for track in self
+            .pose_global_positions
+            .iter_mut()
+            .chain(self.pose_local_positions.iter_mut())
+        {
+            for p in track.iter_mut() {
+                *p *= factor;
+            }
+        }
+        for joint in metadata.joints.iter_mut() {
+            if let Some(endsite) = joint.endsite.as_mut() {
+                endsite.offset *= factor;
+            }
+        }
+    }
+
+    /// Convert the skeleton and the animation to meters, assuming they are currently in `assumed_unit`.
+    pub fn to_meters(&mut self, metadata: &mut BvhMetadata, assumed_unit: Unit) {
+        self.rescale(metadata, assumed_unit.meters_per_unit());
+    }
+
+    /// Height of the rest pose skeleton (End Sites included) in file units.
+    ///
+    /// Rest poses aren't always upright (some files lay the spine along Z and rely on the root rotation), so the largest extent of the bounding box is used.
+    pub fn rest_height(&self, metadata: &BvhMetadata) -> f64 {
+        let endsites = metadata.joints.iter().filter_map(|joint| {
+            joint
+                .endsite
+                .as_ref()
+                .map(|endsite| self.rest_global_positions[joint.index] + endsite.offset)
+        });
+        let mut min = Position::new(f64::MAX, f64::MAX, f64::MAX);
+        let mut max = Position::new(f64::MIN, f64::MIN, f64::MIN);
+        for p in self.rest_global_positions.iter().copied().chain(endsites) {
+            min = Position::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Position::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let extent = max - min;
+        extent.x.max(extent.y).max(extent.z)
+    }
+
+    /// Guess the unit of the file from the skeleton height (assuming it's a human-sized skeleton).
+    ///
+    /// Picks the unit for which the skeleton height is closest (on a log scale) to 1.7 meters.
+    pub fn guess_unit(&self, metadata: &BvhMetadata) -> Unit {
+        let height = self.rest_height(metadata);
+        let distance = |unit: &Unit| (height * unit.meters_per_unit() / TYPICAL_SKELETON_HEIGHT).ln().abs();
+        *Unit::ALL
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap()
+    }
+}
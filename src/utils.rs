@@ -1,4 +1,5 @@
-use crate::types::Quaternion;
+use crate::types::{Position, Quaternion};
+use cgmath::{InnerSpace, Matrix, Matrix3, Zero};
 
 
 /// reorder vector based on euler angles order string
@@ -72,3 +73,149 @@ pub(crate) fn __from_euler_to_quat(x: f64, y: f64, z: f64, order: &str) -> Quate
 }
 
 
+
+/// Quaternion logarithm of a unit quaternion (i.e. rotation axis scaled by HALF of the rotation angle).
+pub(crate) fn __quat_log(q: Quaternion) -> Position {
+    let v_len = q.v.magnitude();
+    if v_len < 1e-12 {
+        return Position::zero();
+    }
+    let half_angle = v_len.atan2(q.s);
+    q.v * (half_angle / v_len)
+}
+
+/// Quaternion exponent (inverse of `__quat_log`).
+pub(crate) fn __quat_exp(v: Position) -> Quaternion {
+    let half_angle = v.magnitude();
+    if half_angle < 1e-12 {
+        return Quaternion::new(1.0, v.x, v.y, v.z).normalize();
+    }
+    Quaternion::from_sv(half_angle.cos(), v * (half_angle.sin() / half_angle))
+}
+
+/// Return `q` or `-q` (the same rotation), whichever lies in the same hemisphere as `reference`.
+pub(crate) fn __same_hemisphere(q: Quaternion, reference: Quaternion) -> Quaternion {
+    if q.dot(reference) < 0.0 {
+        -q
+    } else {
+        q
+    }
+}
+
+/// Normalized Gaussian kernel (radius = 3 sigma).
+pub(crate) fn __gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil().max(0.0) as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.into_iter().map(|w| w / sum).collect()
+}
+
+/// Convolve a position track with a (symmetric, odd-sized) kernel. Borders are handled by renormalizing the kernel.
+pub(crate) fn __convolve_positions(track: &[Position], kernel: &[f64]) -> Vec<Position> {
+    let radius = (kernel.len() / 2) as isize;
+    let n = track.len() as isize;
+    (0..n)
+        .map(|i| {
+            let mut sum = Position::zero();
+            let mut weight = 0.0;
+            for (k, w) in kernel.iter().enumerate() {
+                let j = i + k as isize - radius;
+                if j >= 0 && j < n {
+                    sum += track[j as usize] * *w;
+                    weight += w;
+                }
+            }
+            sum / weight
+        })
+        .collect()
+}
+
+/// Convolve a rotation track with a (symmetric, odd-sized) kernel.
+/// Averaging happens in the tangent space of every frame (i.e. on `log(q_i^-1 * q_j)`), so it's safe for large rotations and sign flips.
+pub(crate) fn __convolve_rotations(track: &[Quaternion], kernel: &[f64]) -> Vec<Quaternion> {
+    let radius = (kernel.len() / 2) as isize;
+    let n = track.len() as isize;
+    (0..n)
+        .map(|i| {
+            let q_i = track[i as usize];
+            let q_i_inv = q_i.conjugate();
+            let mut sum = Position::zero();
+            let mut weight = 0.0;
+            for (k, w) in kernel.iter().enumerate() {
+                let j = i + k as isize - radius;
+                if j >= 0 && j < n {
+                    let delta = q_i_inv * track[j as usize];
+                    let delta = if delta.s < 0.0 { -delta } else { delta };
+                    sum += __quat_log(delta) * *w;
+                    weight += w;
+                }
+            }
+            (q_i * __quat_exp(sum / weight)).normalize()
+        })
+        .collect()
+}
+
+/// Twist part of the swing-twist decomposition of `q` around `axis` (unit vector), i.e. the rotation of `q` around that axis only.
+pub(crate) fn __twist_about_axis(q: Quaternion, axis: Position) -> Quaternion {
+    let twist = Quaternion::from_sv(q.s, axis * q.v.dot(axis));
+    if twist.magnitude2() < 1e-12 {
+        // 180 degrees swing - twist is undefined
+        return Quaternion::new(1.0, 0.0, 0.0, 0.0);
+    }
+    twist.normalize()
+}
+
+/// Shortest rotation taking direction `from` to direction `to` (neither has to be normalized).
+/// For opposite directions it's a 180 degrees rotation around the X, Y or Z axis, whichever is the most perpendicular to `from`.
+pub(crate) fn __rotation_between(from: Position, to: Position) -> Quaternion {
+    if from.magnitude2() < 1e-24 || to.magnitude2() < 1e-24 {
+        return Quaternion::new(1.0, 0.0, 0.0, 0.0);
+    }
+    let from = from.normalize();
+    let to = to.normalize();
+    let dot = from.dot(to);
+    if dot < -0.999999 {
+        let axis = [Position::unit_y(), Position::unit_z(), Position::unit_x()]
+            .into_iter()
+            .min_by(|a, b| a.dot(from).abs().total_cmp(&b.dot(from).abs()))
+            .unwrap();
+        // remove the component along `from` so that the rotation axis is exactly perpendicular
+        let axis = (axis - from * axis.dot(from)).normalize();
+        return Quaternion::from_sv(0.0, axis);
+    }
+    // half-way quaternion trick: (1 + dot, from x to) normalized
+    Quaternion::from_sv(1.0 + dot, from.cross(to)).normalize()
+}
+
+/// Rotation best mapping directions `from` onto directions `to` (TRIAD method using the longest and the most non-collinear vector).
+/// Pairs where either vector is (nearly) zero are skipped, e.g. a degenerate bone or two coincident joint positions.
+/// Returns `None` if there are no usable pairs. With only collinear pairs the twist around them is taken from `reference`.
+pub(crate) fn __fit_rotation(from: &[Position], to: &[Position], reference: Quaternion) -> Option<Quaternion> {
+    let usable: Vec<usize> = (0..from.len().min(to.len()))
+        .filter(|&i| from[i].magnitude2() > 1e-12 && to[i].magnitude2() > 1e-12)
+        .collect();
+    let first = usable.iter().copied().max_by(|&a, &b| from[a].magnitude2().total_cmp(&from[b].magnitude2()))?;
+    let a1 = from[first].normalize();
+    let b1 = to[first].normalize();
+    // both the source and the target pair have to span a plane, otherwise the TRIAD frame is undefined
+    let second = usable
+        .iter()
+        .map(|&i| (i, a1.cross(from[i].normalize()).magnitude().min(b1.cross(to[i].normalize()).magnitude())))
+        .filter(|(_, sin)| *sin > 1e-3)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    let Some((second, _)) = second else {
+        // single direction - keep the twist of the reference rotation
+        return Some(__rotation_between(reference * a1, b1) * reference);
+    };
+    let frame = |v1: Position, v2: Position| {
+        let x = v1;
+        let y = v1.cross(v2).normalize();
+        Matrix3::from_cols(x, y, x.cross(y))
+    };
+    let a = frame(a1, from[second]);
+    let b = frame(b1, to[second]);
+    Some(Quaternion::from(b * a.transpose()).normalize())
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use bvh_anim_parser::blend::RotationBlend;
+use bvh_anim_parser::clip::RootAlignment;
+use bvh_anim_parser::mask::JointMask;
+use cgmath::InnerSpace;
+
+#[test]
+fn blend_and_crossfade() {
+    let (metadata, data) = common::load_sword_attack();
+    let (metadata_a, data_a) = data.slice(&metadata, 0..200);
+    let (metadata_b, data_b) = data.slice(&metadata, 300..500);
+
+    let (metadata_blend, _) = data_a.blend(&metadata_a, &data_b, &metadata_b, 0.3, RotationBlend::Slerp);
+    assert_eq!(metadata_blend.num_frames, 200);
+
+    let (metadata_fade, data_fade) =
+        data_a.crossfade(&metadata_a, &data_b, &metadata_b, 40, RotationBlend::Nlerp, RootAlignment::Position);
+    assert_eq!(metadata_fade.num_frames, 360);
+    assert!((data_fade.pose_global_positions[3][10] - data_a.pose_global_positions[3][10]).magnitude() < 1e-9);
+}
+
+#[test]
+fn masked_blend_and_additive_layers() {
+    let (metadata, data) = common::load_sword_attack();
+    let (metadata_a, data_a) = data.slice(&metadata, 0..200);
+    let (metadata_b, data_b) = data.slice(&metadata, 300..500);
+
+    let upper_body = JointMask::subtree(&metadata_a, "Spine2");
+    let (_, data_masked) = data_a.blend_masked(&metadata_a, &data_b, &metadata_b, &upper_body, RotationBlend::Slerp);
+    let left_leg = metadata.find_joint_by_name("LeftUpLeg").index;
+    assert!((data_masked.pose_local_rotations[left_leg][50] - data_a.pose_local_rotations[left_leg][50]).magnitude() < 1e-9);
+
+    let soft_upper_body = JointMask::subtree_with_falloff(&metadata_a, "Spine2", 2);
+    let spine2 = metadata.find_joint_by_name("Spine2").index;
+    assert_eq!(upper_body.weight(spine2), 1.0);
+    assert!(soft_upper_body.weight(spine2) < 1.0);
+
+    let layer = data_b.make_additive(&metadata_b, &data_b.pose_at_frame(0));
+    let (metadata_add, _) = data_a.apply_additive(&metadata_a, &layer, 0.5, &upper_body);
+    assert_eq!(metadata_add.num_frames, 200);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use bvh_anim_parser::builder::{AnimationBuilder, SkeletonBuilder};
+use bvh_anim_parser::types::Position;
+use cgmath::InnerSpace;
+
+#[test]
+fn build_two_bone_arm() {
+    let (arm_metadata, arm_data) = SkeletonBuilder::new("Shoulder", Position::new(0.0, 0.0, 0.0))
+        .joint("Elbow", "Shoulder", Position::new(10.0, 0.0, 0.0))
+        .endsite("Elbow", Position::new(10.0, 0.0, 0.0))
+        .animation(1.0 / 60.0)
+        .push_frame_euler(Position::new(0.0, 0.0, 0.0), &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .push_frame_euler(Position::new(0.0, 0.0, 0.0), &[90.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .build();
+    assert_eq!((arm_metadata.num_frames, arm_metadata.fps), (2, 60));
+    assert!((arm_data.pose_global_positions[1][1] - Position::new(0.0, 10.0, 0.0)).magnitude() < 1e-9);
+}
+
+#[test]
+fn rebuilding_parsed_animation_gives_same_result() {
+    let (metadata, data) = common::load_sword_attack();
+    let mut builder = AnimationBuilder::new(&metadata, &data, metadata.frame_time);
+    for frame in 0..metadata.num_frames {
+        let local_rotations: Vec<_> = data.pose_local_rotations.iter().map(|track| track[frame]).collect();
+        builder = builder.push_frame(data.pose_global_positions[0][frame], &local_rotations);
+    }
+    let (built_metadata, built_data) = builder.build();
+    assert_eq!(built_metadata.num_frames, metadata.num_frames);
+    assert!((built_data.pose_global_positions[10][5] - data.pose_global_positions[10][5]).magnitude() < 1e-9);
+    assert!((built_data.rest_global_rotations[7] - data.rest_global_rotations[7]).magnitude() < 1e-9);
+}
+
+#[test]
+fn joints_are_ordered_depth_first() {
+    // LeftLeg is added after Spine, but ends up right after its parent
+    let (metadata, data) = SkeletonBuilder::new("Hips", Position::new(0.0, 100.0, 0.0))
+        .joint("LeftUpLeg", "Hips", Position::new(10.0, 0.0, 0.0))
+        .joint("Spine", "Hips", Position::new(0.0, 10.0, 0.0))
+        .joint("LeftLeg", "LeftUpLeg", Position::new(0.0, -40.0, 0.0))
+        .endsite("LeftLeg", Position::new(0.0, -40.0, 0.0))
+        .endsite("Spine", Position::new(0.0, 30.0, 0.0))
+        .build();
+    let names: Vec<&str> = metadata.joints.iter().map(|joint| joint.name.as_str()).collect();
+    assert_eq!(names, ["Hips", "LeftUpLeg", "LeftLeg", "Spine"]);
+    assert_eq!(metadata.get_kinematic_chains(), vec![vec![0, 1, 2], vec![3]]);
+    let left_leg = metadata.find_joint_by_name("LeftLeg").index;
+    assert!((data.rest_global_positions[left_leg] - Position::new(10.0, 60.0, 0.0)).magnitude() < 1e-9);
+}
+
+#[test]
+fn animation_builder_channel_orders() {
+    let (metadata, data) = common::load_sword_attack();
+    // 90 degrees around X: the X value comes first with XYZ channels, second with the default ZXY channels
+    let zxy = AnimationBuilder::new(&metadata, &data, metadata.frame_time);
+    let num_channels = metadata.joints.len() * 3;
+    let mut channels = vec![0.0; num_channels];
+    channels[3 + 1] = 90.0;
+    let (_, zxy_data) = zxy.push_frame_euler(Position::new(0.0, 0.0, 0.0), &channels).build();
+    let xyz = AnimationBuilder::new(&metadata, &data, metadata.frame_time).channels(&metadata.joints[1].name, "XYZ");
+    let mut channels = vec![0.0; num_channels];
+    channels[3] = 90.0;
+    let (_, xyz_data) = xyz.push_frame_euler(Position::new(0.0, 0.0, 0.0), &channels).build();
+    assert!((zxy_data.pose_local_rotations[1][0] - xyz_data.pose_local_rotations[1][0]).magnitude() < 1e-9);
+    assert!((xyz_data.pose_local_rotations[1][0].s - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+}
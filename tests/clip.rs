@@ -0,0 +1,25 @@
+mod common;
+
+use bvh_anim_parser::clip::RootAlignment;
+
+#[test]
+fn slice_concat_reverse_repeat() {
+    let (metadata, data) = common::load_sword_attack();
+    let (metadata_a, data_a) = data.slice(&metadata, 0..100);
+    let (metadata_b, data_b) = data.slice(&metadata, 300..400);
+    assert_eq!(metadata_a.num_frames, 100);
+
+    let (metadata_ab, data_ab) = data_a.concat(&metadata_a, &data_b, &metadata_b, RootAlignment::PositionAndHeading);
+    assert_eq!(metadata_ab.num_frames, 200);
+    // the root continues with the last step of the first clip instead of stopping at the seam
+    let end_a = data_a.pose_global_positions[0][99];
+    let step_a = end_a - data_a.pose_global_positions[0][98];
+    let start_b = data_ab.pose_global_positions[0][100];
+    assert!((end_a.x + step_a.x - start_b.x).abs() < 1e-9 && (end_a.z + step_a.z - start_b.z).abs() < 1e-9);
+
+    let (metadata_rev, data_rev) = data_ab.reverse(&metadata_ab);
+    assert_eq!(data_rev.pose_global_positions[5][0], data_ab.pose_global_positions[5][199]);
+
+    let (metadata_loop, _) = data_rev.repeat(&metadata_rev, 3, RootAlignment::Position);
+    assert_eq!(metadata_loop.num_frames, 600);
+}
@@ -0,0 +1,7 @@
+use bvh_anim_parser::parse::load_bvh_from_file;
+use bvh_anim_parser::types::{BvhData, BvhMetadata};
+
+/// The bundled sword attack animation (centimeters, 120 fps, 598 frames).
+pub fn load_sword_attack() -> (BvhMetadata, BvhData) {
+    load_bvh_from_file("./examples/test_anim_sword_attack.bvh")
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use bvh_anim_parser::compress::CompressionSettings;
+
+#[test]
+fn compression_stays_within_tolerances() {
+    let (metadata, data) = common::load_sword_attack();
+    for quantization_bits in [None, Some(16)] {
+        let settings = CompressionSettings { max_position_error: 0.5, max_angle_error: 0.01, quantization_bits };
+        let (compressed, report) = data.compress(&metadata, &settings);
+        assert!(report.compression_ratio > 1.0);
+        assert!(report.max_position_error <= settings.max_position_error + 1e-6);
+        assert!(report.max_angle_error <= settings.max_angle_error + 1e-6);
+
+        let (decompressed_metadata, decompressed_data) = compressed.decompress(&metadata, &data);
+        assert_eq!(decompressed_metadata.num_frames, metadata.num_frames);
+        assert_eq!(decompressed_data.pose_local_rotations[0].len(), metadata.num_frames);
+    }
+}
+
+#[test]
+fn frame_indices_sized_by_frame_count() {
+    let (metadata, data) = common::load_sword_attack();
+    let settings = CompressionSettings { max_position_error: 0.5, max_angle_error: 0.01, quantization_bits: None };
+    // up to 256 frames fit into 1-byte indices, the whole clip (598 frames) needs 2 bytes
+    let (short_metadata, short_data) = data.slice(&metadata, 0..256);
+    for (metadata, data, index_bytes) in [(&short_metadata, &short_data, 1), (&metadata, &data, 2)] {
+        let (compressed, report) = data.compress(metadata, &settings);
+        let rotation_keys = compressed.num_keys() - compressed.root_positions.frames.len();
+        let expected = compressed.root_positions.frames.len() * (12 + index_bytes) + rotation_keys * (16 + index_bytes);
+        assert_eq!(report.compressed_bytes, expected);
+    }
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use bvh_anim_parser::contact::ContactSettings;
+
+#[test]
+fn humanoid_foot_contacts() {
+    let (metadata, data) = common::load_sword_attack();
+    // the rest pose of the file isn't a standing pose
+    let (metadata, data) = data.rebase_rest_pose(&metadata, 0);
+    let settings = ContactSettings::for_character_height(170.0);
+    let contacts = data.detect_humanoid_foot_contacts(&metadata, &settings);
+    for name in ["LeftFoot", "LeftToeBase", "RightFoot", "RightToeBase"] {
+        let labels = contacts.of_joint(metadata.find_joint_by_name(name).index).unwrap();
+        assert_eq!(labels.len(), metadata.num_frames);
+        assert!(labels.iter().any(|&c| c));
+    }
+
+    let csv = contacts.to_csv(&metadata);
+    assert!(csv.starts_with("frame,LeftFoot,LeftToeBase,RightFoot,RightToeBase"));
+    assert_eq!(csv.lines().count(), metadata.num_frames + 1);
+}
+
+#[test]
+fn raised_foot_has_no_contacts() {
+    let (metadata, data) = common::load_sword_attack();
+    let (metadata, mut data) = data.rebase_rest_pose(&metadata, 0);
+    let left_foot = metadata.find_joint_by_name("LeftFoot").index;
+    let left_toes = metadata.find_joint_by_name("LeftToeBase").index;
+    // the left foot hovers 20 cm above the ground for the whole clip
+    for joint in [left_foot, left_toes] {
+        for position in data.pose_global_positions[joint].iter_mut() {
+            position.y += 20.0;
+        }
+    }
+    let contacts = data.detect_humanoid_foot_contacts(&metadata, &ContactSettings::for_character_height(170.0));
+    assert!(contacts.of_joint(left_foot).unwrap().iter().all(|&c| !c));
+    assert!(contacts.of_joint(left_toes).unwrap().iter().all(|&c| !c));
+    let right_foot = metadata.find_joint_by_name("RightFoot").index;
+    assert!(contacts.of_joint(right_foot).unwrap().iter().any(|&c| c));
+}
+
+#[test]
+fn rest_clearances_and_ground_height() {
+    let (metadata, data) = common::load_sword_attack();
+    let (metadata, data) = data.rebase_rest_pose(&metadata, 0);
+    let clearance = |name: &str| data.rest_clearance(&metadata, metadata.find_joint_by_name(name).index);
+    // ankles stand ~6 cm above the ground, toes touch it
+    assert!((clearance("LeftFoot") - 5.731).abs() < 1e-3);
+    assert!((clearance("LeftToeBase") - 0.029).abs() < 1e-3);
+    assert!((clearance("RightFoot") - 6.406).abs() < 1e-3);
+    assert!((clearance("RightToeBase") - 0.442).abs() < 1e-3);
+    // the character stands on Y = 0
+    let contacts = data.detect_humanoid_foot_contacts(&metadata, &ContactSettings::for_character_height(170.0));
+    assert!(contacts.ground_height.abs() < 0.2);
+}
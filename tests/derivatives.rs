@@ -0,0 +1,28 @@
+mod common;
+
+use bvh_anim_parser::derivatives::{Difference, RotationSpace};
+use cgmath::{InnerSpace, Rotation3};
+
+#[test]
+fn velocity_tracks_have_frame_shape() {
+    let (metadata, data) = common::load_sword_attack();
+    let velocities = data.linear_velocities(&metadata, Difference::Central, None);
+    let accelerations = data.linear_accelerations(&metadata, Difference::Central, Some(2.0));
+    assert_eq!((velocities.len(), velocities[0].len()), (metadata.joints.len(), metadata.num_frames));
+    assert_eq!(accelerations[0].len(), metadata.num_frames);
+}
+
+#[test]
+fn forward_differences_integrate_to_next_frame() {
+    let (metadata, data) = common::load_sword_attack();
+    let forward = data.linear_velocities(&metadata, Difference::Forward, None);
+    let next = data.pose_global_positions[10][20] + forward[10][20] * metadata.frame_time;
+    assert!((next - data.pose_global_positions[10][21]).magnitude() < 1e-6);
+
+    let angular = data.angular_velocities(&metadata, RotationSpace::Global, Difference::Forward, None);
+    let omega = angular[10][20] * metadata.frame_time;
+    let step = cgmath::Quaternion::from_axis_angle(omega.normalize(), cgmath::Rad(omega.magnitude()));
+    let next = step * data.pose_global_rotations[10][20];
+    let target = data.pose_global_rotations[10][21];
+    assert!((next - target).magnitude() < 1e-6 || (next + target).magnitude() < 1e-6);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use bvh_anim_parser::filter::Filter;
+use bvh_anim_parser::types::Position;
+use cgmath::InnerSpace;
+
+#[test]
+fn quaternion_continuity() {
+    let (_, mut data) = common::load_sword_attack();
+    data.enforce_quaternion_continuity();
+    for track in data.pose_local_rotations.iter() {
+        assert!(track.windows(2).all(|q| q[0].dot(q[1]) >= 0.0));
+    }
+}
+
+#[test]
+fn filters_keep_motion_close() {
+    let (metadata, data) = common::load_sword_attack();
+    let filters = [
+        Filter::Gaussian { sigma: 2.0 },
+        Filter::SavitzkyGolay { window: 9, order: 3 },
+        Filter::Butterworth { cutoff: 6.0 },
+        Filter::OneEuro { min_cutoff: 1.0, beta: 0.01, derivative_cutoff: 1.0 },
+    ];
+    for filter in filters {
+        let smoothed = data.filter_rotations(&metadata, filter).filter_root_positions(&metadata, filter);
+        let error = smoothed.pose_global_positions[10][40] - data.pose_global_positions[10][40];
+        assert!(error.magnitude() < 10.0);
+    }
+}
+
+#[test]
+fn savitzky_golay_keeps_quadratic() {
+    let parabola: Vec<Position> = (0..20).map(|i| Position::new((i * i) as f64, i as f64, 1.0)).collect();
+    let filtered = Filter::SavitzkyGolay { window: 7, order: 2 }.apply_to_positions(&parabola, 1.0 / 30.0);
+    assert!(filtered.iter().zip(parabola.iter()).all(|(a, b)| (a - b).magnitude() < 1e-6));
+}
+
+#[test]
+fn rotation_filtering_ignores_sign_flips() {
+    let (metadata, mut data) = common::load_sword_attack();
+    data.enforce_quaternion_continuity();
+    let track = &data.pose_local_rotations[10];
+    let flipped: Vec<_> = track.iter().enumerate().map(|(i, &q)| if i % 2 == 0 { q } else { -q }).collect();
+    let a = Filter::Gaussian { sigma: 2.0 }.apply_to_rotations(&flipped, metadata.frame_time);
+    let b = Filter::Gaussian { sigma: 2.0 }.apply_to_rotations(track, metadata.frame_time);
+    assert!(a.iter().zip(b.iter()).all(|(a, b)| a.dot(*b).abs() > 1.0 - 1e-9));
+}
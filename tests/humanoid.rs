@@ -0,0 +1,32 @@
+mod common;
+
+use bvh_anim_parser::humanoid::{HumanoidBone, HumanoidPreset};
+use bvh_anim_parser::retarget::JointMap;
+
+#[test]
+fn humanoid_mapping_by_names() {
+    let (metadata, _) = common::load_sword_attack();
+    let mapping = metadata.humanoid_mapping();
+    assert_eq!(mapping.preset, HumanoidPreset::SwordAttack);
+    assert!(mapping.missing_bones.is_empty());
+    let left_forearm = mapping.get(HumanoidBone::LeftLowerArm).unwrap();
+    assert_eq!(metadata.joints[left_forearm].name, "LeftForeArm");
+
+    let unmapped: Vec<&str> = mapping.unmapped_joints.iter().map(|&i| metadata.joints[i].name.as_str()).collect();
+    assert!(unmapped.contains(&"Spine1") && unmapped.contains(&"LeftHandThumb1"));
+
+    let joint_map = JointMap::between_humanoids(&metadata, &metadata);
+    assert_eq!(joint_map.pairs.len(), HumanoidBone::ALL.len());
+}
+
+#[test]
+fn humanoid_detection_by_topology() {
+    let (metadata, data) = common::load_sword_attack();
+    let detection = data.detect_humanoid(&metadata, Some(0));
+    let mapping = metadata.humanoid_mapping_with_preset(HumanoidPreset::SwordAttack);
+    for assignment in detection.assignments.iter() {
+        assert_eq!(mapping.get(assignment.bone), Some(assignment.joint));
+        assert!(assignment.confidence > 0.5);
+    }
+    assert_eq!(detection.assignments.len(), HumanoidBone::ALL.len());
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use bvh_anim_parser::contact::ContactSettings;
+use bvh_anim_parser::filter::Filter;
+use bvh_anim_parser::ik::{AngleLimits, IkChain, IkSolver, TwoBoneChain};
+use bvh_anim_parser::types::{BvhData, BvhMetadata, Position};
+use cgmath::InnerSpace;
+
+fn left_leg(metadata: &BvhMetadata) -> TwoBoneChain {
+    TwoBoneChain {
+        root: metadata.find_joint_by_name("LeftUpLeg").index,
+        middle: metadata.find_joint_by_name("LeftLeg").index,
+        end: metadata.find_joint_by_name("LeftFoot").index,
+    }
+}
+
+#[test]
+fn two_bone_ik_reaches_target() {
+    let (metadata, data) = common::load_sword_attack();
+    let chain = left_leg(&metadata);
+    let mut lifted = data.clone();
+    let target = data.pose_global_positions[chain.end][10] + Position::new(0.0, 10.0, 0.0);
+    let knee = data.pose_global_positions[chain.middle][10];
+    let foot_rotation = data.pose_global_rotations[chain.end][10];
+    lifted.solve_two_bone_ik(&metadata, 10, chain, target, Some(foot_rotation), Some(knee));
+    assert!((lifted.pose_global_positions[chain.end][10] - target).magnitude() < 1e-6);
+    assert!((lifted.pose_global_rotations[chain.end][10] - foot_rotation).magnitude() < 1e-6);
+}
+
+#[test]
+fn foot_skate_cleanup_pins_feet() {
+    let (metadata, data) = common::load_sword_attack();
+    let chain = left_leg(&metadata);
+    let (standing_metadata, standing_data) = data.rebase_rest_pose(&metadata, 0);
+    let contacts = standing_data.detect_humanoid_foot_contacts(&standing_metadata, &ContactSettings::for_character_height(170.0));
+    let sliding = data.filter_root_positions(&metadata, Filter::Gaussian { sigma: 8.0 });
+    let fixed = sliding.fix_humanoid_foot_skate(&metadata, &contacts, 5);
+    let slide = |data: &BvhData| {
+        let labels = contacts.of_joint(chain.end).unwrap();
+        (1..metadata.num_frames)
+            .filter(|&frame| labels[frame] && labels[frame - 1])
+            .map(|frame| (data.pose_global_positions[chain.end][frame] - data.pose_global_positions[chain.end][frame - 1]).magnitude())
+            .sum::<f64>()
+    };
+    assert!(slide(&fixed) < 1e-6);
+    assert!(slide(&sliding) > slide(&fixed));
+}
+
+#[test]
+fn chain_solvers_reach_target() {
+    let (metadata, data) = common::load_sword_attack();
+    let shoulder = metadata.find_joint_by_name("RightShoulder").index;
+    let hand = metadata.find_joint_by_name("RightHand").index;
+    let chain = IkChain::from_joints(&metadata, shoulder, hand);
+    assert_eq!(chain.joints.len(), 4);
+    let target = data.pose_global_positions[hand][50] + Position::new(0.0, 10.0, 0.0);
+    for solver in [IkSolver::Ccd { iterations: 50, tolerance: 1e-3 }, IkSolver::Fabrik { iterations: 50, tolerance: 1e-3 }] {
+        let mut solved = data.clone();
+        solved.solve_ik(&metadata, 50, &chain, solver, target, None);
+        assert!((solved.pose_global_positions[hand][50] - target).magnitude() < 1e-2);
+        assert!((solved.pose_global_positions[hand][49] - data.pose_global_positions[hand][49]).magnitude() < 1e-9);
+    }
+}
+
+#[test]
+fn ccd_respects_angle_limits() {
+    let (metadata, data) = common::load_sword_attack();
+    let shoulder = metadata.find_joint_by_name("RightShoulder").index;
+    let elbow = metadata.find_joint_by_name("RightForeArm").index;
+    let hand = metadata.find_joint_by_name("RightHand").index;
+    let locked = AngleLimits { min: Position::new(0.0, 0.0, 0.0), max: Position::new(0.0, 0.0, 0.0) };
+    let chain = IkChain::from_joints(&metadata, shoulder, hand).with_limits(elbow, locked);
+    let target = data.pose_global_positions[hand][50] + Position::new(0.0, 10.0, 0.0);
+    let mut solved = data.clone();
+    solved.solve_ik(&metadata, 50, &chain, IkSolver::Ccd { iterations: 20, tolerance: 1e-3 }, target, None);
+    assert!((solved.pose_local_rotations[elbow][50] - cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0)).magnitude() < 1e-9);
+}
+
+#[test]
+fn fabrik_respects_angle_limits() {
+    let (metadata, data) = common::load_sword_attack();
+    let shoulder = metadata.find_joint_by_name("RightShoulder").index;
+    let elbow = metadata.find_joint_by_name("RightForeArm").index;
+    let hand = metadata.find_joint_by_name("RightHand").index;
+    let target = data.pose_global_positions[hand][50] + Position::new(0.0, 10.0, 0.0);
+    // the elbow bends by about -1.2 rad around Z at this frame
+    let hinge = AngleLimits { min: Position::new(-0.1, -0.1, -1.3), max: Position::new(0.1, 0.1, -1.0) };
+    let locked = AngleLimits { min: Position::new(0.0, 0.0, 0.0), max: Position::new(0.0, 0.0, 0.0) };
+    for limits in [hinge, locked] {
+        let chain = IkChain::from_joints(&metadata, shoulder, hand).with_limits(elbow, limits);
+        let mut solved = data.clone();
+        solved.solve_ik(&metadata, 50, &chain, IkSolver::Fabrik { iterations: 50, tolerance: 1e-3 }, target, None);
+        let local = solved.pose_local_rotations[elbow][50];
+        assert!((limits.clamp(local) - local).magnitude() < 1e-9);
+    }
+    // within the hinge the shoulder and the arm still get the hand closer
+    let chain = IkChain::from_joints(&metadata, shoulder, hand).with_limits(elbow, hinge);
+    let mut solved = data.clone();
+    solved.solve_ik(&metadata, 50, &chain, IkSolver::Fabrik { iterations: 50, tolerance: 1e-3 }, target, None);
+    assert!((solved.pose_global_positions[hand][50] - target).magnitude() < 10.0);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use bvh_anim_parser::keypoints::{import_keypoints, parse_keypoints_csv, parse_keypoints_json, CoordinateSystem, KeypointLayout};
+use bvh_anim_parser::types::Position;
+use cgmath::InnerSpace;
+
+const H36M_JOINTS: [&str; 17] = [
+    "Hips", "RightUpLeg", "RightLeg", "RightFoot", "LeftUpLeg", "LeftLeg", "LeftFoot", "Spine", "Spine3", "Neck", "Head", "LeftArm",
+    "LeftForeArm", "LeftHand", "RightArm", "RightForeArm", "RightHand",
+];
+
+#[test]
+fn import_human36m_keypoints() {
+    let (metadata, data) = common::load_sword_attack();
+    let joints: Vec<usize> = H36M_JOINTS.iter().map(|name| metadata.find_joint_by_name(name).index).collect();
+    let frames: Vec<Vec<Position>> = (0..metadata.num_frames)
+        .map(|frame| joints.iter().map(|&joint| data.pose_global_positions[joint][frame]).collect())
+        .collect();
+    let mut settings = KeypointLayout::Human36M.default_settings();
+    settings.coordinate_system = CoordinateSystem::YUp;
+    let (keypoints_metadata, keypoints_data) = import_keypoints(KeypointLayout::Human36M, &frames, &settings);
+    assert_eq!(keypoints_metadata.joints.len(), 17);
+    assert_eq!(keypoints_metadata.num_frames, metadata.num_frames);
+    let forearm = keypoints_metadata.find_joint_by_name("LeftForeArm").index;
+    let hand = keypoints_metadata.find_joint_by_name("LeftHand").index;
+    for frame in [0, 100, 300] {
+        let imported = keypoints_data.pose_global_positions[hand][frame] - keypoints_data.pose_global_positions[forearm][frame];
+        let original = frames[frame][13] - frames[frame][12];
+        assert!(imported.normalize().dot(original.normalize()) > 0.999);
+    }
+}
+
+#[test]
+fn parse_json_and_csv() {
+    let parsed = parse_keypoints_json(r#"{"frames": [[[0, 1, 2], {"x": 3, "y": 4, "z": 5}]]}"#);
+    assert_eq!(parsed, vec![vec![Position::new(0.0, 1.0, 2.0), Position::new(3.0, 4.0, 5.0)]]);
+    let parsed = parse_keypoints_csv("frame,x0,y0,z0,x1,y1,z1\n0,0,1,2,3,4,5\n", 2);
+    assert_eq!(parsed, vec![vec![Position::new(0.0, 1.0, 2.0), Position::new(3.0, 4.0, 5.0)]]);
+}
+
+#[test]
+#[should_panic(expected = "CSV line 3")]
+fn csv_with_malformed_row_panics() {
+    parse_keypoints_csv("frame,x0,y0,z0\n0,0,1,2\n1,0,n/a,2\n2,0,1,2\n", 1);
+}
+
+#[test]
+fn import_undetected_keypoints() {
+    let (metadata, data) = common::load_sword_attack();
+    let joints: Vec<usize> = H36M_JOINTS.iter().map(|name| metadata.find_joint_by_name(name).index).collect();
+    let mut frames: Vec<Vec<Position>> = (0..metadata.num_frames)
+        .map(|frame| joints.iter().map(|&joint| data.pose_global_positions[joint][frame]).collect())
+        .collect();
+    // the left wrist is lost for a while, the right elbow coincides with the right wrist
+    for frame in frames.iter_mut().take(120).skip(100) {
+        frame[13] = Position::new(0.0, 0.0, 0.0);
+        frame[15] = frame[16];
+    }
+    let mut settings = KeypointLayout::Human36M.default_settings();
+    settings.coordinate_system = CoordinateSystem::YUp;
+    let (keypoints_metadata, keypoints_data) = import_keypoints(KeypointLayout::Human36M, &frames, &settings);
+
+    for track in keypoints_data.pose_global_rotations.iter() {
+        assert!(track.iter().all(|q| q.s.is_finite() && q.v.x.is_finite() && q.v.y.is_finite() && q.v.z.is_finite()));
+    }
+    // the left forearm bone keeps its direction from the last frame the wrist was seen
+    let forearm = keypoints_metadata.find_joint_by_name("LeftForeArm").index;
+    let hand = keypoints_metadata.find_joint_by_name("LeftHand").index;
+    let held = keypoints_data.pose_global_positions[hand][99] - keypoints_data.pose_global_positions[forearm][99];
+    for frame in 100..120 {
+        let bone = keypoints_data.pose_global_positions[hand][frame] - keypoints_data.pose_global_positions[forearm][frame];
+        assert!(bone.normalize().dot(held.normalize()) > 0.999);
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use bvh_anim_parser::looping::LoopSettings;
+use bvh_anim_parser::types::{Position, Quaternion};
+use cgmath::InnerSpace;
+
+/// The root rotation relative to its heading (rotation around the Y axis).
+fn tilt(q: Quaternion) -> Quaternion {
+    let twist = Quaternion::new(q.s, 0.0, q.v.y, 0.0).normalize();
+    twist.conjugate() * q
+}
+
+fn ground_step(positions: &[Position], frame: usize) -> Position {
+    let step = positions[frame + 1] - positions[frame];
+    Position::new(step.x, 0.0, step.z)
+}
+
+#[test]
+fn find_make_and_repeat_loop() {
+    let (metadata, data) = common::load_sword_attack();
+    let settings = LoopSettings { min_length: 30, max_length: None, velocity_weight: 0.01 };
+    let best = data.find_loop(&metadata, &settings).unwrap();
+    assert!(best.end - best.start >= 30);
+
+    let (loop_metadata, loop_data) = data.make_loop(&metadata, best.start, best.end, 10);
+    assert_eq!(loop_metadata.num_frames, best.end - best.start + 1);
+    let last = loop_metadata.num_frames - 1;
+    for joint in 1..metadata.joints.len() {
+        let difference = loop_data.pose_local_rotations[joint][last] - loop_data.pose_local_rotations[joint][0];
+        assert!(difference.magnitude() < 1e-6);
+    }
+
+    let (repeated_metadata, repeated_data) = loop_data.repeat_loop(&loop_metadata, 3);
+    assert_eq!(repeated_metadata.num_frames, 3 * last + 1);
+    let root_positions = &repeated_data.pose_global_positions[0];
+    let root_rotations = &repeated_data.pose_local_rotations[0];
+    for cycle in 1..=3 {
+        // every cycle ends with the root at the height and tilt of the first frame
+        let end = cycle * last;
+        assert!((root_positions[end].y - root_positions[0].y).abs() < 1e-6);
+        let (a, b) = (tilt(root_rotations[end]), tilt(root_rotations[0]));
+        assert!((a - b).magnitude().min((a + b).magnitude()) < 1e-6);
+        if cycle == 3 {
+            break;
+        }
+
+        // the root neither stops nor jumps when the next cycle starts
+        let before = ground_step(root_positions, end - 2).magnitude();
+        let seam = ground_step(root_positions, end - 1).magnitude();
+        let after = ground_step(root_positions, end).magnitude();
+        assert!(seam > 0.5 * before.min(after) && seam < 2.0 * before.max(after));
+    }
+}
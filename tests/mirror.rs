@@ -0,0 +1,18 @@
+mod common;
+
+use bvh_anim_parser::mirror::{MirrorAxis, NamePairing};
+use cgmath::InnerSpace;
+
+#[test]
+fn mirror_swaps_and_reflects_sides() {
+    let (metadata, data) = common::load_sword_attack();
+    let pairing = NamePairing::default();
+    let mirrored = data.mirror(&metadata, MirrorAxis::X, &pairing).expect("skeleton is symmetric");
+    let pairs = metadata.mirror_pairs(&pairing).unwrap();
+    let left_hand = metadata.find_joint_by_name("LeftHand").index;
+    assert_eq!(metadata.joints[pairs[left_hand]].name, "RightHand");
+
+    let p = data.pose_global_positions[pairs[left_hand]][100];
+    let p_mirrored = mirrored.pose_global_positions[left_hand][100];
+    assert!((p_mirrored - cgmath::Vector3::new(-p.x, p.y, p.z)).magnitude() < 1e-3);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use bvh_anim_parser::reconstruct::reconstruct_from_positions;
+use cgmath::InnerSpace;
+
+#[test]
+fn reconstruct_reproduces_positions() {
+    let (metadata, data) = common::load_sword_attack();
+    let (reconstructed_metadata, reconstructed_data) =
+        reconstruct_from_positions(&metadata, &data.rest_local_positions, &data.pose_global_positions);
+    assert_eq!(reconstructed_metadata.num_frames, metadata.num_frames);
+    for joint in 0..metadata.joints.len() {
+        for frame in [0, 100, 300] {
+            let error = reconstructed_data.pose_global_positions[joint][frame] - data.pose_global_positions[joint][frame];
+            assert!(error.magnitude() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn reconstruct_skips_degenerate_bones() {
+    let (metadata, data) = common::load_sword_attack();
+    let hips = metadata.find_joint_by_name("Hips").index;
+    let right_up_leg = metadata.find_joint_by_name("RightUpLeg").index;
+    let left_fore_arm = metadata.find_joint_by_name("LeftForeArm").index;
+    let left_hand = metadata.find_joint_by_name("LeftHand").index;
+    let head = metadata.find_joint_by_name("Head").index;
+
+    // collapse the left forearm (its joint has no other bone) and the right hip bone (hips still have the spine and the left leg)
+    let mut positions = data.pose_global_positions.clone();
+    positions[left_hand] = positions[left_fore_arm].clone();
+    positions[right_up_leg] = positions[hips].clone();
+    let (_, reconstructed_data) = reconstruct_from_positions(&metadata, &data.rest_local_positions, &positions);
+
+    for track in reconstructed_data.pose_global_rotations.iter() {
+        assert!(track.iter().all(|q| q.s.is_finite() && q.v.x.is_finite() && q.v.y.is_finite() && q.v.z.is_finite()));
+    }
+    // the forearm keeps the rotation of its parent
+    for frame in [0, 100, 300] {
+        let forearm = reconstructed_data.pose_global_rotations[left_fore_arm][frame];
+        let arm = reconstructed_data.pose_global_rotations[metadata.joints[left_fore_arm].parent_index as usize][frame];
+        assert!(forearm.dot(arm).abs() > 1.0 - 1e-9);
+        // the rest of the skeleton is still fitted
+        let error = reconstructed_data.pose_global_positions[head][frame] - data.pose_global_positions[head][frame];
+        assert!(error.magnitude() < 1e-6);
+    }
+}
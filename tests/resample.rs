@@ -0,0 +1,18 @@
+mod common;
+
+#[test]
+fn resample_down_and_up() {
+    let (metadata, data) = common::load_sword_attack();
+    let (metadata_30fps, data_30fps) = data.resample(&metadata, 30.0);
+    assert_eq!(metadata_30fps.fps, 30);
+    assert_eq!(metadata_30fps.num_frames, 150);
+    assert_eq!(data_30fps.pose_local_rotations[0].len(), 150);
+    let (metadata_60fps, _) = data_30fps.resample(&metadata_30fps, 60.0);
+    assert_eq!(metadata_60fps.num_frames, 299);
+}
+
+#[test]
+fn fps_is_rounded() {
+    let (metadata, _) = common::load_sword_attack();
+    assert_eq!(metadata.fps, 120);
+}
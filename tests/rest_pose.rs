@@ -0,0 +1,32 @@
+mod common;
+
+use cgmath::InnerSpace;
+
+#[test]
+fn rebase_rest_pose_to_frame() {
+    let (metadata, data) = common::load_sword_attack();
+    let (_, rebased_data) = data.rebase_rest_pose(&metadata, 0);
+    for joint in 0..metadata.joints.len() {
+        let rest = rebased_data.rest_global_positions[joint] - rebased_data.rest_global_positions[0];
+        let frame0 = data.pose_global_positions[joint][0] - data.pose_global_positions[0][0];
+        assert!((rest - frame0).magnitude() < 1e-6);
+        let error = rebased_data.pose_global_positions[joint][30] - data.pose_global_positions[joint][30];
+        assert!(error.magnitude() < 1e-6);
+    }
+}
+
+#[test]
+fn t_pose_and_a_pose() {
+    let (metadata, data) = common::load_sword_attack();
+    let (rebased_metadata, rebased_data) = data.rebase_rest_pose(&metadata, 0);
+    let (t_metadata, t_data) = rebased_data.rest_to_t_pose(&rebased_metadata);
+    let elbow = t_metadata.find_joint_by_name("LeftForeArm").index;
+    let shoulder = t_metadata.find_joint_by_name("LeftArm").index;
+    let upper_arm = (t_data.rest_global_positions[elbow] - t_data.rest_global_positions[shoulder]).normalize();
+    assert!(upper_arm.y.abs() < 1e-6);
+    assert!((t_data.pose_global_positions[elbow][30] - data.pose_global_positions[elbow][30]).magnitude() < 1e-6);
+
+    let (_, a_data) = rebased_data.rest_to_a_pose(&rebased_metadata, 45.0);
+    let upper_arm = (a_data.rest_global_positions[elbow] - a_data.rest_global_positions[shoulder]).normalize();
+    assert!((upper_arm.y + 45f64.to_radians().sin()).abs() < 1e-6);
+}
@@ -0,0 +1,22 @@
+mod common;
+
+use bvh_anim_parser::retarget::JointMap;
+use bvh_anim_parser::units::Unit;
+use cgmath::InnerSpace;
+
+#[test]
+fn retarget_to_rescaled_skeleton() {
+    let (metadata, data) = common::load_sword_attack();
+    let (mut target_metadata, mut target_data) = common::load_sword_attack();
+    target_data.to_meters(&mut target_metadata, Unit::Centimeters);
+
+    let joint_map = JointMap::by_same_names(&metadata, &target_metadata);
+    let custom_map = JointMap::from_pairs(&[("Hips", "Hips"), ("Spine", "Spine")]);
+    assert_eq!(custom_map.pairs.len(), 2);
+
+    let (retargeted_metadata, retargeted_data) = data.retarget(&metadata, &target_metadata, &target_data, &joint_map, None);
+    assert_eq!(retargeted_metadata.num_frames, metadata.num_frames);
+    let head = metadata.find_joint_by_name("Head").index;
+    let error = retargeted_data.pose_global_positions[head][200] - data.pose_global_positions[head][200] * 0.01;
+    assert!(error.magnitude() < 1e-6);
+}
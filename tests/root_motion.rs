@@ -0,0 +1,34 @@
+mod common;
+
+use cgmath::InnerSpace;
+
+#[test]
+fn extract_trajectory_and_in_place_round_trip() {
+    let (metadata, data) = common::load_sword_attack();
+    let forward = data.root_forward_axis(&metadata);
+    let trajectory = data.extract_root_trajectory(&metadata, forward);
+    assert_eq!(trajectory.positions.len(), metadata.num_frames);
+    assert_eq!(trajectory.positions[10].y, 0.0);
+
+    let (trajectory, in_place) = data.to_in_place(&metadata, forward);
+    assert!(in_place.pose_global_positions[0][300].x.abs() < 1e-9);
+    let restored = in_place.apply_root_trajectory(&metadata, &trajectory);
+    assert!((restored.pose_global_positions[20][300] - data.pose_global_positions[20][300]).magnitude() < 1e-6);
+}
+
+#[test]
+fn character_frame_encoding_round_trip() {
+    let (metadata, data) = common::load_sword_attack();
+    let forward = data.root_forward_axis(&metadata);
+    let encoding = data.encode_character_frame(&metadata, forward);
+    assert_eq!(encoding.num_frames(), metadata.num_frames);
+    assert_eq!(encoding.frame_features(100).len(), 3 + metadata.joints.len() * (3 + 4));
+
+    let decoded = encoding.decode(&metadata, &data);
+    for joint in metadata.joints.iter() {
+        for frame in 0..metadata.num_frames {
+            let error = decoded.pose_global_positions[joint.index][frame] - data.pose_global_positions[joint.index][frame];
+            assert!(error.magnitude() < 1e-6);
+        }
+    }
+}
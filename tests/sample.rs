@@ -0,0 +1,54 @@
+mod common;
+
+use bvh_anim_parser::sample::{Interpolation, WrapMode};
+use bvh_anim_parser::types::{Pose, Quaternion};
+use cgmath::InnerSpace;
+
+#[test]
+fn sample_between_frames_interpolates_root() {
+    let (metadata, data) = common::load_sword_attack();
+    let time = 23.5 * metadata.frame_time;
+    let pose = data.sample(&metadata, time, WrapMode::Clamp, Interpolation::Linear);
+    let hips_pos = (data.pose_global_positions[0][23] + data.pose_global_positions[0][24]) / 2.0;
+    assert!((pose.root_position - hips_pos).magnitude() < 1e-9);
+}
+
+#[test]
+fn sample_at_frame_after_looping_gives_that_frame() {
+    let (metadata, data) = common::load_sword_attack();
+    let time = metadata.num_frames as f64 * metadata.frame_time + 23.0 * metadata.frame_time;
+    let pose = data.sample(&metadata, time, WrapMode::Loop, Interpolation::Cubic);
+    let frame = data.pose_at_frame(23);
+    for (a, b) in pose.global_positions.iter().zip(frame.global_positions.iter()) {
+        assert!((a - b).magnitude() < 1e-6);
+    }
+}
+
+#[test]
+fn pose_past_the_end_gives_last_frame() {
+    let (metadata, data) = common::load_sword_attack();
+    let last = data.pose_at_frame(metadata.num_frames - 1);
+    let past = data.pose_at_frame(metadata.num_frames + 10);
+    assert_eq!(past.global_positions, last.global_positions);
+}
+
+#[test]
+fn empty_animation_gives_rest_pose() {
+    let (metadata, data) = common::load_sword_attack();
+    let (empty_metadata, empty_data) = data.slice(&metadata, 0..0);
+    assert_eq!(empty_metadata.num_frames, 0);
+    for pose in [
+        empty_data.pose_at_frame(0),
+        empty_data.sample(&empty_metadata, 1.0, WrapMode::Loop, Interpolation::Cubic),
+        empty_data.sample(&empty_metadata, 0.0, WrapMode::Clamp, Interpolation::Linear),
+    ] {
+        assert_eq!(pose.global_positions, data.rest_global_positions);
+        assert!(pose.local_rotations.iter().all(|&q| q == Quaternion::new(1.0, 0.0, 0.0, 0.0)));
+        // consistent with forward kinematics of its own rotations
+        let recalculated = Pose::new(&metadata, &data, pose.root_position, pose.local_rotations.clone());
+        for (a, b) in recalculated.global_positions.iter().zip(pose.global_positions.iter()) {
+            assert!((a - b).magnitude() < 1e-9);
+        }
+        assert_eq!(recalculated.global_rotations, pose.global_rotations);
+    }
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use bvh_anim_parser::skeleton::RenameError;
+use bvh_anim_parser::types::{Position, Quaternion};
+use cgmath::{InnerSpace, Rad, Rotation3};
+
+#[test]
+fn keep_joints_keeps_motion() {
+    let (metadata, data) = common::load_sword_attack();
+    let (small_metadata, small_data, remap) =
+        data.keep_joints(&metadata, |joint| !joint.name.contains("Thumb") && !joint.name.ends_with("HandEnd"));
+    assert_eq!(small_metadata.joints.len(), metadata.joints.len() - 6);
+    let right_hand = small_metadata.find_joint_by_name("RightHand");
+    assert!(right_hand.is_leaf && right_hand.children.is_empty());
+    for (old, new) in remap.iter().enumerate() {
+        if let Some(new) = *new {
+            for frame in 0..metadata.num_frames {
+                let error = small_data.pose_global_positions[new][frame] - data.pose_global_positions[old][frame];
+                assert!(error.magnitude() < 1e-6);
+            }
+        }
+    }
+}
+
+#[test]
+fn remove_joint_keeps_rotations() {
+    let (metadata, data) = common::load_sword_attack();
+    let spine1 = metadata.find_joint_by_name("Spine1").index;
+    let (small_metadata, small_data, remap) = data.remove_joints(&metadata, &[spine1]);
+    assert_eq!(remap[spine1], None);
+    let neck = small_metadata.find_joint_by_name("Neck").index;
+    let old_neck = metadata.find_joint_by_name("Neck").index;
+    let error = small_data.pose_global_rotations[neck][0] - data.pose_global_rotations[old_neck][0];
+    assert!(error.magnitude() < 1e-6);
+}
+
+#[test]
+fn remove_rotated_joint_keeps_positions() {
+    let (metadata, mut data) = common::load_sword_attack();
+    let spine1 = metadata.find_joint_by_name("Spine1").index;
+    // Spine1 bent by a constant rotation (away from its rest rotation) in every frame
+    let bend = Quaternion::from_axis_angle(Position::new(1.0, 0.0, 1.0).normalize(), Rad(0.7));
+    for rotation in data.pose_local_rotations[spine1].iter_mut() {
+        *rotation = bend;
+    }
+    data.recalculate_global_pose(&metadata);
+
+    let (_, small_data, remap) = data.remove_joints(&metadata, &[spine1]);
+    for (old, new) in remap.iter().enumerate() {
+        let Some(new) = *new else { continue };
+        for frame in [0, 100, 300] {
+            let error = small_data.pose_global_positions[new][frame] - data.pose_global_positions[old][frame];
+            assert!(error.magnitude() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn add_and_insert_root_joint() {
+    let (metadata, data) = common::load_sword_attack();
+    let hand = metadata.find_joint_by_name("RightHand").index;
+    let (edited_metadata, edited_data, remap) = data.add_joint(&metadata, hand, "Sword", Position::new(0.0, 100.0, 0.0));
+    let sword = edited_metadata.find_joint_by_name("Sword");
+    assert_eq!(sword.parent_index, remap[hand] as isize);
+    assert!(sword.is_leaf && sword.depth == edited_metadata.joints[remap[hand]].depth + 1);
+    assert!((edited_data.pose_global_positions[sword.index][0] - edited_data.pose_global_positions[remap[hand]][0]).magnitude() > 99.0);
+
+    let hips_height = data.rest_global_positions[0].y;
+    let (edited_metadata, edited_data, remap) = data.insert_root_joint(&metadata, "Root", Position::new(0.0, hips_height, 0.0));
+    assert_eq!(edited_metadata.joints[0].name, "Root");
+    assert!((edited_data.pose_global_positions[remap[0]][0] - data.pose_global_positions[0][0]).magnitude() < 1e-6);
+}
+
+#[test]
+fn rename_joints_by_regex() {
+    let (mut metadata, _) = common::load_sword_attack();
+    assert_eq!(metadata.rename_joints("^", "sword:"), Ok(metadata.joints.len()));
+    assert_eq!(metadata.joints[0].name, "sword:Hips");
+}
+
+#[test]
+fn rename_joints_to_duplicates_renames_nothing() {
+    let (mut metadata, _) = common::load_sword_attack();
+    let names: Vec<String> = metadata.joints.iter().map(|joint| joint.name.clone()).collect();
+    // LeftArm and RightArm would both become Arm
+    let result = metadata.rename_joints("^(Left|Right)", "");
+    assert!(matches!(result, Err(RenameError::DuplicateName { .. })));
+    assert!(metadata.joints.iter().zip(names.iter()).all(|(joint, name)| &joint.name == name));
+
+    assert!(matches!(metadata.rename_joints("(", ""), Err(RenameError::InvalidPattern(_))));
+    let hips = metadata.find_joint_by_name("Hips").index;
+    assert!(metadata.rename_joint(hips, "Spine").is_err());
+    assert_eq!(metadata.joints[hips].name, "Hips");
+}
+
+#[test]
+fn reparent_joint_keeps_world_rotation() {
+    let (metadata, data) = common::load_sword_attack();
+    let hand = metadata.find_joint_by_name("RightHand").index;
+    let left_hand = metadata.find_joint_by_name("LeftHand").index;
+    let (edited_metadata, edited_data, remap) = data.reparent_joint(&metadata, left_hand, hand);
+    assert_eq!(edited_metadata.joints[remap[left_hand]].parent_index, remap[hand] as isize);
+    let error = edited_data.pose_global_rotations[remap[left_hand]][0] - data.pose_global_rotations[left_hand][0];
+    assert!(error.magnitude() < 1e-6);
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use bvh_anim_parser::units::Unit;
+use cgmath::InnerSpace;
+
+#[test]
+fn guesses_unit_from_skeleton_height() {
+    let (metadata, data) = common::load_sword_attack();
+    assert_eq!(data.guess_unit(&metadata), Unit::Centimeters);
+}
+
+#[test]
+fn to_meters_and_rescale_scale_positions() {
+    let (mut metadata, mut data) = common::load_sword_attack();
+    let hips_pos = data.pose_global_positions[0][23];
+    data.to_meters(&mut metadata, Unit::Centimeters);
+    assert!((data.pose_global_positions[0][23] - hips_pos * 0.01).magnitude() < 1e-9);
+    data.rescale(&mut metadata, 100.0);
+    assert!((data.pose_global_positions[0][23] - hips_pos).magnitude() < 1e-9);
+}